@@ -0,0 +1,253 @@
+use std::path::{Path, PathBuf};
+
+use crate::ui::theme::Theme;
+
+///
+/// ### A file to open, with where to put the cursor
+///
+/// `line` and `col` are 0-based, `line` is `usize::MAX` for "the last line".
+///
+#[derive(Debug, PartialEq)]
+pub struct FileArg {
+    pub path: String,
+    pub line: Option<usize>,
+    pub col: Option<usize>,
+}
+
+///
+/// ### Parsed command line
+///
+#[derive(Debug, Default)]
+pub struct Options {
+    pub files: Vec<FileArg>,
+    pub readonly: bool,
+    pub stdout: bool,
+    pub config: Option<PathBuf>,
+    pub theme: Option<String>,
+    pub keymap: Option<PathBuf>,
+    pub diff: Option<(String, String)>,
+    pub check_config: bool,
+    pub help: bool,
+    pub version: bool,
+}
+
+pub fn usage() -> String {
+    format!("\
+Usage: pte [options] [file ...]
+
+Files:
+  file                  open file in its own buffer
+  file:line[:col]       open file at a position, as printed by compilers and grep
+  +line file            open file at line (a lone + goes to the last line)
+  -                     read a buffer from stdin
+  --                    treat everything after it as files
+
+Options:
+  -R, --readonly        open files read-only
+      --stdout          write the active buffer to stdout on exit
+      --config <file>   read options from <file> instead of the user config
+      --theme <name>    color theme ({})
+      --keymap <file>   key bindings, a JSON object of \"key\": \"action\" pairs
+      --diff <a> <b>    open two files side by side and show their differences
+      --check-config    check the config, keymap and theme, then exit
+  -h, --help            print this help
+  -V, --version         print the version
+", Theme::names())
+}
+
+/**
+ * Parse the arguments after the program name
+ *
+ * Errors are one line, meant to be printed after "pte: ".
+ */
+pub fn parse(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    let mut jump: Option<(String, Option<usize>)> = None;
+    let mut only_files = false;
+
+    while let Some(arg) = args.next() {
+        if only_files || arg == "-" || !arg.starts_with(['-', '+']) {
+            let mut file = if only_files || arg == "-" {
+                FileArg { path: arg, line: None, col: None }
+            } else {
+                split_position(&arg)
+            };
+            if let Some((_, line)) = jump.take() {
+                file.line = line.or(Some(usize::MAX));
+                file.col = None;
+            }
+            options.files.push(file);
+            continue;
+        }
+
+        if let Some(line) = arg.strip_prefix('+') {
+            let line = match line {
+                "" => None,
+                _ => Some(line.parse::<usize>().map_err(|_| format!("invalid line number '{}'", arg))?.saturating_sub(1)),
+            };
+            jump = Some((arg, line));
+            continue;
+        }
+
+        // --option=value is the same as --option value
+        let (name, mut inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |what: &str| {
+            inline.take().or_else(|| args.next())
+                .filter(|value| !value.is_empty())
+                .ok_or_else(|| format!("{} needs {}", name, what))
+        };
+
+        match name.as_str() {
+            "--" => only_files = true,
+            "-h" | "--help" => options.help = true,
+            "-V" | "--version" => options.version = true,
+            "-R" | "--readonly" => options.readonly = true,
+            "--stdout" => options.stdout = true,
+            "--check-config" => options.check_config = true,
+            "--config" => options.config = Some(PathBuf::from(value("a file")?)),
+            "--keymap" => options.keymap = Some(PathBuf::from(value("a file")?)),
+            "--theme" => {
+                let theme = value("a theme name")?;
+                if Theme::by_name(&theme).is_none() {
+                    return Err(format!("unknown theme '{}' (expected {})", theme, Theme::names()));
+                }
+                options.theme = Some(theme);
+            }
+            "--diff" => {
+                let a = value("two files")?;
+                let b = value("two files")?;
+                options.diff = Some((a, b));
+            }
+            _ => return Err(format!("unknown option '{}'", name)),
+        }
+        if inline.is_some() {
+            return Err(format!("{} doesn't take a value", name));
+        }
+    }
+
+    if let Some((arg, _)) = jump {
+        return Err(format!("{} needs a file after it", arg));
+    }
+    if options.diff.is_some() && !options.files.is_empty() {
+        return Err(String::from("--diff can't be combined with other files"));
+    }
+    if options.stdout && options.diff.is_some() {
+        return Err(String::from("--stdout can't be combined with --diff"));
+    }
+    Ok(options)
+}
+
+/**
+ * Split a `file:line:col` argument, like compilers and `grep -n` print them
+ *
+ * A file that exists under the full name is taken as is, so names with
+ * colons in them still open. Lines and columns are 1-based here.
+ */
+fn split_position(arg: &str) -> FileArg {
+    let whole = FileArg { path: arg.to_string(), line: None, col: None };
+    if Path::new(arg).exists() {
+        return whole;
+    }
+
+    // grep -n leaves a colon after the line number
+    let trimmed = arg.strip_suffix(':').unwrap_or(arg);
+    let mut parts = trimmed.rsplitn(3, ':');
+    let last = parts.next().and_then(|part| part.parse::<usize>().ok());
+    let middle = parts.next();
+    let rest = parts.next();
+
+    match (last, middle.and_then(|part| part.parse::<usize>().ok()), rest) {
+        // file:line:col
+        (Some(col), Some(line), Some(path)) if !path.is_empty() => FileArg {
+            path: path.to_string(),
+            line: Some(line.saturating_sub(1)),
+            col: Some(col.saturating_sub(1)),
+        },
+        // file:line, where "file" may itself contain a colon
+        (Some(line), _, _) => match trimmed.rsplit_once(':') {
+            Some((path, _)) if !path.is_empty() => FileArg {
+                path: path.to_string(),
+                line: Some(line.saturating_sub(1)),
+                col: None,
+            },
+            _ => whole,
+        },
+        _ => whole,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn file(path: &str, line: Option<usize>, col: Option<usize>) -> FileArg {
+        FileArg { path: path.to_string(), line, col }
+    }
+
+    #[test]
+    fn splits_positions() {
+        assert_eq!(split_position("src/main.rs:12:5"), file("src/main.rs", Some(11), Some(4)));
+        assert_eq!(split_position("src/main.rs:12:"), file("src/main.rs", Some(11), None));
+        assert_eq!(split_position("src/main.rs"), file("src/main.rs", None, None));
+        assert_eq!(split_position(":12"), file(":12", None, None));
+    }
+
+    #[test]
+    fn keeps_colons_in_paths() {
+        assert_eq!(split_position("a:b.txt"), file("a:b.txt", None, None));
+        assert_eq!(split_position("dir:x/notes.txt:7"), file("dir:x/notes.txt", Some(6), None));
+
+        // a file that exists under the full name isn't split
+        let path = std::env::temp_dir().join(format!("pte-cli-{}:3", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let name = path.to_string_lossy().to_string();
+        let split = split_position(&name);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(split, file(&name, None, None));
+    }
+
+    #[test]
+    fn jumps_to_lines() {
+        let options = parse(args(&["+3", "a.txt", "+", "b.txt", "c.txt:4:2"])).unwrap();
+        assert_eq!(options.files, vec![
+            file("a.txt", Some(2), None),
+            file("b.txt", Some(usize::MAX), None),
+            file("c.txt", Some(3), Some(1)),
+        ]);
+        assert_eq!(parse(args(&["+x", "a.txt"])).unwrap_err(), "invalid line number '+x'");
+        assert_eq!(parse(args(&["a.txt", "+3"])).unwrap_err(), "+3 needs a file after it");
+    }
+
+    #[test]
+    fn reads_stdin_and_files_after_double_dash() {
+        let options = parse(args(&["-", "--", "-R", "+3", "x.txt:2"])).unwrap();
+        assert_eq!(options.files, vec![
+            file("-", None, None),
+            file("-R", None, None),
+            file("+3", None, None),
+            file("x.txt:2", None, None),
+        ]);
+        assert!(!options.readonly);
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = parse(args(&["--config=my.toml", "--keymap", "keys.json", "-R", "--stdout"])).unwrap();
+        assert_eq!(options.config, Some(PathBuf::from("my.toml")));
+        assert_eq!(options.keymap, Some(PathBuf::from("keys.json")));
+        assert!(options.readonly && options.stdout);
+
+        assert_eq!(parse(args(&["--config="])).unwrap_err(), "--config needs a file");
+        assert_eq!(parse(args(&["--stdout=yes"])).unwrap_err(), "--stdout doesn't take a value");
+        assert_eq!(parse(args(&["--frob"])).unwrap_err(), "unknown option '--frob'");
+        assert_eq!(parse(args(&["--diff", "a"])).unwrap_err(), "--diff needs two files");
+    }
+}
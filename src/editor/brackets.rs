@@ -0,0 +1,230 @@
+use super::language::{self, Comments};
+use std::{collections::hash_map::DefaultHasher, hash::{Hash, Hasher}};
+
+/// Lines searched for a match above and below the cursor
+const SEARCH_LINES: usize = 2_000;
+
+/// A bracket in a buffer: line, byte offset and the bracket itself
+type Token = (usize, usize, char);
+
+const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+fn is_open(c: char) -> bool {
+    PAIRS.iter().any(|(open, _)| *open == c)
+}
+
+fn pair_of(c: char) -> Option<(char, char)> {
+    PAIRS.iter().find(|(open, close)| *open == c || *close == c).copied()
+}
+
+///
+/// ### What isn't code in a language
+///
+/// Brackets inside strings and comments don't count when matching.
+///
+pub struct Syntax {
+    comments: Comments,
+    quotes: Vec<char>,
+    /// Whether ' starts a char literal or a lifetime, as in Rust
+    char_literals: bool,
+}
+
+impl Syntax {
+    pub fn new(filetype: Option<&str>) -> Self {
+        let quotes = language::pairs(filetype).iter()
+            .filter(|(open, close)| open == close)
+            .map(|(quote, _)| *quote)
+            .collect();
+        let char_literals = filetype == Some("rust");
+        Self { comments: language::comments(filetype), quotes, char_literals }
+    }
+
+    /**
+     * Brackets of `line` outside strings and comments
+     *
+     * `in_comment` is whether the line starts inside a block comment, and
+     * is left at whether the next one does. Strings end with their line.
+     */
+    fn brackets(&self, line: &str, in_comment: &mut bool) -> Vec<(usize, char)> {
+        let mut found = Vec::new();
+        let mut quote = None;
+        let mut chars = line.char_indices();
+        while let Some((i, c)) = chars.next() {
+            let rest = &line[i..];
+            if *in_comment {
+                if let Some((_, end)) = self.comments.block.filter(|(_, end)| rest.starts_with(end)) {
+                    *in_comment = false;
+                    chars.nth(end.chars().count().saturating_sub(2));
+                }
+                continue;
+            }
+            if let Some(open) = quote {
+                match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    _ if c == open => quote = None,
+                    _ => {}
+                }
+                continue;
+            }
+
+            // before line comments, Lua's --[[ starts with its --
+            if let Some((start, _)) = self.comments.block.filter(|(start, _)| rest.starts_with(start)) {
+                *in_comment = true;
+                chars.nth(start.chars().count().saturating_sub(2));
+                continue;
+            }
+            if self.comments.line.is_some_and(|start| rest.starts_with(start)) {
+                break;
+            }
+            if self.quotes.contains(&c) {
+                quote = Some(c);
+            } else if self.char_literals && c == '\'' {
+                // a lifetime is just the ' and its name
+                if let Some(len) = char_literal(rest) {
+                    chars.nth(len - 2);
+                }
+            } else if pair_of(c).is_some() {
+                found.push((i, c));
+            }
+        }
+        found
+    }
+
+    /// Every bracket around `line` that matching could get to, in order
+    fn tokens(&self, lines: &[String], line: usize) -> Vec<Token> {
+        let start = line.saturating_sub(SEARCH_LINES);
+        let end = lines.len().min(line + SEARCH_LINES + 1);
+        let mut in_comment = false;
+        let mut tokens = Vec::new();
+        for (index, text) in lines.iter().enumerate().take(end).skip(start) {
+            tokens.extend(self.brackets(text, &mut in_comment).into_iter().map(|(col, c)| (index, col, c)));
+        }
+        tokens
+    }
+}
+
+/**
+ * Length in chars of the char literal `text` starts with, like '{' or '\''
+ *
+ * None when the ' starts a lifetime or label instead.
+ */
+fn char_literal(text: &str) -> Option<usize> {
+    // '\u{10FFFF}' is the longest
+    let chars: Vec<char> = text.chars().take(12).collect();
+    match chars.get(1)? {
+        '\\' => chars.iter().skip(3).position(|c| *c == '\'').map(|end| end + 4),
+        _ => (chars.get(2) == Some(&'\'')).then_some(3),
+    }
+}
+
+/// Index of the token matching `tokens[i]`, if the brackets between them are balanced
+fn match_index(tokens: &[Token], i: usize) -> Option<usize> {
+    let (open, close) = pair_of(tokens[i].2)?;
+    let forward = tokens[i].2 == open;
+    let mut depth = 0;
+    let mut index = i;
+    loop {
+        index = match forward {
+            true => index + 1,
+            false => index.checked_sub(1)?,
+        };
+        let c = tokens.get(index)?.2;
+        if is_open(c) == forward {
+            depth += 1;
+        } else if depth > 0 {
+            depth -= 1;
+        } else {
+            return (c == if forward { close } else { open }).then_some(index);
+        }
+    }
+}
+
+/**
+ * The bracket at (line, col), or right before it, and the one matching it
+ *
+ * Positions are (line, byte offset), the bracket at the cursor first.
+ */
+pub fn matching(lines: &[String], syntax: &Syntax, line: usize, col: usize) -> Option<((usize, usize), (usize, usize))> {
+    let tokens = syntax.tokens(lines, line);
+    let at = |(l, c, _): &Token| *l == line && *c == col;
+    let before = |(l, c, bracket): &Token| *l == line && c + bracket.len_utf8() == col;
+    let i = tokens.iter().position(at).or_else(|| tokens.iter().position(before))?;
+    let j = match_index(&tokens, i)?;
+    Some(((tokens[i].0, tokens[i].1), (tokens[j].0, tokens[j].1)))
+}
+
+///
+/// ### The last bracket match, so it isn't searched for on every frame
+///
+/// It is found again once the cursor, the filetype or a line the search
+/// could reach has changed.
+///
+pub struct MatchCache {
+    key: Option<u64>,
+    found: Option<((usize, usize), (usize, usize))>,
+}
+
+impl MatchCache {
+    pub fn new() -> Self {
+        Self { key: None, found: None }
+    }
+
+    /// Like `matching`, for (line, col) in `lines` of `filetype`
+    pub fn matching(&mut self, lines: &[String], filetype: Option<&str>, line: usize, col: usize) -> Option<((usize, usize), (usize, usize))> {
+        let mut hasher = DefaultHasher::new();
+        (filetype, line, col).hash(&mut hasher);
+        let start = line.saturating_sub(SEARCH_LINES);
+        let end = lines.len().min(line + SEARCH_LINES + 1);
+        lines[start.min(end)..end].hash(&mut hasher);
+        let key = hasher.finish();
+
+        if self.key != Some(key) {
+            self.found = matching(lines, &Syntax::new(filetype), line, col);
+            self.key = Some(key);
+        }
+        self.found
+    }
+}
+
+/**
+ * The innermost pair of brackets around the range `from`..`to`
+ *
+ * The opening bracket is before `from` and the closing one at or after
+ * `to`. Returns the positions of both brackets.
+ */
+pub fn enclosing(lines: &[String], syntax: &Syntax, from: (usize, usize), to: (usize, usize)) -> Option<((usize, usize), (usize, usize))> {
+    let tokens = syntax.tokens(lines, from.0);
+    let split = tokens.iter().position(|(l, c, _)| (*l, *c) >= from).unwrap_or(tokens.len());
+
+    let mut depth = 0;
+    for i in (0..split).rev() {
+        if !is_open(tokens[i].2) {
+            depth += 1;
+            continue;
+        }
+        if depth > 0 {
+            depth -= 1;
+            continue;
+        }
+        let j = match_index(&tokens, i)?;
+        if (tokens[j].0, tokens[j].1) >= to {
+            return Some(((tokens[i].0, tokens[i].1), (tokens[j].0, tokens[j].1)));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_lua_block_comments() {
+        let lines: Vec<String> = ["f(--[[ ) ]] x)", "--[[ (", "]] )"].iter().map(|line| line.to_string()).collect();
+        let syntax = Syntax::new(Some("lua"));
+        assert_eq!(matching(&lines, &syntax, 0, 1), Some(((0, 1), (0, 13))));
+        assert_eq!(syntax.tokens(&lines, 1), vec![(0, 1, '('), (0, 13, ')'), (2, 3, ')')]);
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+use crate::utils::{config::Settings, format::FileFormat};
+
+use super::{
+    cursor::Cursor,
+    history::History,
+    indent::Indent,
+    large::LargeFile,
+};
+
+///
+/// ### A file that is open in the editor
+///
+/// The active buffer lives directly on the `Editor` (so editing code can
+/// keep using `editor.lines`, `editor.cursors`, ...). Inactive buffers are
+/// parked in `editor.buffers` until they are switched to.
+///
+pub struct Buffer {
+    pub lines: Vec<String>,
+    pub cursors: Vec<Cursor>,
+    pub history: History,
+    pub filename: String,
+    pub changes_saved: bool,
+    pub scroll: usize,
+    pub marks: HashMap<char, u16>,
+    pub format: FileFormat,
+    pub read_only: bool,
+    pub large: Option<LargeFile>,
+    pub detected_indent: Indent,
+    pub indent: Indent,
+    /// Options in effect for the buffer, to draw it in other views
+    pub settings: Settings,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Self {
+            lines: vec![String::new()],
+            cursors: vec![Cursor::new()],
+            history: History::new(),
+            filename: String::new(),
+            changes_saved: true,
+            scroll: 0,
+            marks: HashMap::new(),
+            format: FileFormat::new(),
+            read_only: false,
+            large: None,
+            detected_indent: Indent::new(),
+            indent: Indent::new(),
+            settings: Settings::new(),
+        }
+    }
+
+    /// Name to show in buffer lists
+    pub fn display_name(&self) -> String {
+        if self.filename.is_empty() {
+            String::from("[No Name]")
+        } else {
+            self.filename.clone()
+        }
+    }
+}
@@ -1,6 +1,6 @@
 #[derive(PartialEq, Clone, Debug)]
 pub enum Command {
-    NULL,
+    Null,
     GotoLine,
     MoveCursor,
     Find,
@@ -12,11 +12,21 @@ pub enum Command {
     SavePrompt,
     AddChar,
     AddNewLine,
+    #[allow(dead_code)]
     Undo,
+    Ex,
+    FilePicker,
+    BufferList,
+    Explorer,
+    ExplorerPrompt,
+    RecoverPrompt,
+    QuitPrompt,
+    ReloadPrompt,
+    DiffView,
 }
 
 impl Command {
     pub fn new() -> Self {
-        Self::NULL
+        Self::Null
     }
 }
\ No newline at end of file
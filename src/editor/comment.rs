@@ -0,0 +1,147 @@
+use super::{indent, language::Comments};
+
+///
+/// ### A change to one line, made by toggling a comment or indenting
+///
+/// `removed` bytes at `at` are replaced with `inserted`, offsets are into
+/// the line as it was before any edit.
+///
+pub struct Edit {
+    pub line: usize,
+    pub at: usize,
+    pub removed: usize,
+    pub inserted: String,
+}
+
+impl Edit {
+    pub fn insert(line: usize, at: usize, text: String) -> Self {
+        Self { line, at, removed: 0, inserted: text }
+    }
+
+    pub fn remove(line: usize, at: usize, removed: usize) -> Self {
+        Self { line, at, removed, inserted: String::new() }
+    }
+
+    pub fn apply(&self, line: &mut String) {
+        line.replace_range(self.at..self.at + self.removed, &self.inserted);
+    }
+
+    /**
+     * Where byte offset `col` of the line ends up after the edit
+     *
+     * An offset right where text is inserted moves past it when `after`,
+     * so the start of a selection can stay in front of a new marker.
+     */
+    pub fn shift(&self, col: usize, after: bool) -> usize {
+        if col < self.at || (col == self.at && !after && self.removed == 0) {
+            col
+        } else if col < self.at + self.removed {
+            self.at
+        } else {
+            col - self.removed + self.inserted.len()
+        }
+    }
+}
+
+/// Length of `marker` at the start of `text`, with the space after it
+fn marker_at_start(text: &str, marker: &str) -> Option<usize> {
+    let rest = text.strip_prefix(marker)?;
+    Some(marker.len() + rest.starts_with(' ') as usize)
+}
+
+/// Length of `marker` at the end of `text`, with the space before it
+fn marker_at_end(text: &str, marker: &str) -> Option<usize> {
+    let rest = text.strip_suffix(marker)?;
+    Some(marker.len() + rest.ends_with(' ') as usize)
+}
+
+/**
+ * Comment or uncomment the lines `rows` of `lines`
+ *
+ * If every non-blank line is commented the comments are taken off, else
+ * each line gets a marker at the smallest indentation among them, so they
+ * line up. Languages without line comments get a block comment per line.
+ * Returns None when the language has no comments at all.
+ */
+pub fn toggle_lines(lines: &[String], rows: &[usize], comments: Comments) -> Option<Vec<Edit>> {
+    let (start, end) = match (comments.line, comments.block) {
+        (Some(start), _) => (start, None),
+        (None, Some((start, end))) => (start, Some(end)),
+        (None, None) => return None,
+    };
+    let rows: Vec<usize> = rows.iter().copied().filter(|row| !lines[*row].trim().is_empty()).collect();
+
+    let commented = |row: &usize| {
+        let text = lines[*row].trim();
+        text.starts_with(start) && end.is_none_or(|end| text.ends_with(end))
+    };
+    let mut edits = Vec::new();
+    if rows.iter().all(commented) {
+        for row in rows {
+            let line = &lines[row];
+            let at = indent::leading(line).len();
+            if let Some(end) = end {
+                let text = line.trim_end();
+                if let Some(len) = marker_at_end(&text[at..], end) {
+                    edits.push(Edit::remove(row, text.len() - len, len));
+                }
+            }
+            if let Some(len) = marker_at_start(&line[at..], start) {
+                edits.push(Edit::remove(row, at, len));
+            }
+        }
+        return Some(edits);
+    }
+
+    let at = rows.iter().map(|row| indent::leading(&lines[*row]).len()).min().unwrap_or(0);
+    for row in rows {
+        if let Some(end) = end {
+            edits.push(Edit::insert(row, lines[row].trim_end().len(), format!(" {}", end)));
+        }
+        edits.push(Edit::insert(row, at, format!("{} ", start)));
+    }
+    Some(edits)
+}
+
+/**
+ * Put a block comment around `from`..`to`, or take off the one there
+ *
+ * An empty range stands for the text of its line. Whitespace around the
+ * markers of an existing comment is allowed. Returns None when the language
+ * has no block comments.
+ */
+pub fn toggle_block(lines: &[String], from: (usize, usize), to: (usize, usize), comments: Comments) -> Option<Vec<Edit>> {
+    let (start, end) = comments.block?;
+    let (from, to) = match from == to {
+        true => {
+            let line = &lines[from.0];
+            ((from.0, indent::leading(line).len()), (from.0, line.trim_end().len()))
+        }
+        false => (from, to),
+    };
+
+    let first = &lines[from.0][from.1..];
+    let last = &lines[to.0][..to.1];
+    let open = first.len() - first.trim_start().len();
+    let close = last.len() - last.trim_end().len();
+    let covered = (from.0, from.1 + open) < (to.0, to.1 - close);
+    let (opening, closing) = (&first[open..], &last[..last.len() - close]);
+
+    if let (true, Some(start_len), Some(mut end_len)) = (covered, marker_at_start(opening, start), marker_at_end(closing, end)) {
+        let inner = (to.1 - close).saturating_sub(from.1 + open);
+        // in "/**/" or "/* */" the markers share the line, and maybe a space
+        if from.0 != to.0 || start.len() + end.len() <= inner {
+            if from.0 == to.0 && start_len + end_len > inner {
+                end_len = end.len();
+            }
+            return Some(vec![
+                Edit::remove(to.0, to.1 - close - end_len, end_len),
+                Edit::remove(from.0, from.1 + open, start_len),
+            ]);
+        }
+    }
+    Some(vec![
+        Edit::insert(to.0, to.1, format!(" {}", end)),
+        Edit::insert(from.0, from.1, format!("{} ", start)),
+    ])
+}
@@ -0,0 +1,152 @@
+use std::{
+    env, fs,
+    path::PathBuf,
+};
+
+///
+/// ### Shell-like path completion for prompts
+///
+/// The first Tab completes the longest common prefix of all matches,
+/// the next ones cycle through the candidates. Directories are shown
+/// with a trailing `/` so completion can continue into them.
+///
+pub struct PathCompleter {
+    pub candidates: Vec<String>,
+    pub selected: Option<usize>,
+    /// Directory part of the input, as the user typed it (`~` not expanded)
+    dir_part: String,
+    /// The input we last produced, used to tell if the user kept tabbing
+    last_completion: Option<String>,
+}
+
+impl PathCompleter {
+    pub fn new() -> Self {
+        Self {
+            candidates: Vec::new(),
+            selected: None,
+            dir_part: String::new(),
+            last_completion: None,
+        }
+    }
+
+    /// Forget the current candidates, called whenever the input is edited
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.last_completion.is_some()
+    }
+
+    /// Complete `input`, returning the new input text
+    ///
+    /// `forward` is false when cycling backwards (Shift + Tab)
+    pub fn complete(&mut self, input: &str, forward: bool) -> String {
+        if self.last_completion.as_deref() == Some(input) && !self.candidates.is_empty() {
+            return self.cycle(forward);
+        }
+
+        let (dir_part, prefix) = match input.rfind('/') {
+            Some(i) => (&input[..=i], &input[i + 1..]),
+            None => ("", input),
+        };
+        self.dir_part = dir_part.to_string();
+        self.candidates = list_candidates(dir_part, prefix);
+        self.selected = None;
+
+        let completion = match self.candidates.len() {
+            0 => input.to_string(),
+            1 => format!("{}{}", self.dir_part, self.candidates[0]),
+            _ => {
+                let common = longest_common_prefix(&self.candidates);
+                if common.len() > prefix.len() {
+                    format!("{}{}", self.dir_part, common)
+                } else {
+                    // nothing more in common, start cycling right away
+                    self.last_completion = Some(input.to_string());
+                    return self.cycle(forward);
+                }
+            }
+        };
+
+        // a single directory match is done, the next tab should look inside it
+        if self.candidates.len() == 1 {
+            self.candidates.clear();
+        }
+        self.last_completion = Some(completion.clone());
+        completion
+    }
+
+    fn cycle(&mut self, forward: bool) -> String {
+        let len = self.candidates.len();
+        let next = match (self.selected, forward) {
+            (None, true) => 0,
+            (None, false) => len - 1,
+            (Some(i), true) => (i + 1) % len,
+            (Some(i), false) => (i + len - 1) % len,
+        };
+        self.selected = Some(next);
+
+        let completion = format!("{}{}", self.dir_part, self.candidates[next]);
+        self.last_completion = Some(completion.clone());
+        completion
+    }
+}
+
+/// Replace a leading `~` with the user's home directory
+pub fn expand_tilde(path: &str) -> String {
+    if path == "~" || path.starts_with("~/") {
+        if let Some(home) = env::var_os("HOME") {
+            return format!("{}{}", home.to_string_lossy(), &path[1..]);
+        }
+    }
+    path.to_string()
+}
+
+/// Entries of `dir_part` starting with `prefix`, directories suffixed with `/`
+fn list_candidates(dir_part: &str, prefix: &str) -> Vec<String> {
+    let dir = if dir_part.is_empty() {
+        PathBuf::from(".")
+    } else {
+        PathBuf::from(expand_tilde(dir_part))
+    };
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut candidates: Vec<String> = entries.flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            // follow symlinks so links to directories complete like directories
+            let is_dir = entry.path().is_dir();
+            Some(if is_dir { name + "/" } else { name })
+        })
+        .collect();
+
+    candidates.sort();
+    candidates
+}
+
+fn longest_common_prefix(strings: &[String]) -> String {
+    let first = match strings.first() {
+        Some(first) => first,
+        None => return String::new(),
+    };
+
+    let mut end = first.len();
+    for s in &strings[1..] {
+        end = first.char_indices()
+            .zip(s.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map(|((i, a), _)| i + a.len_utf8())
+            .unwrap_or(0)
+            .min(end);
+    }
+    first[..end].to_string()
+}
@@ -1,9 +1,11 @@
 #[derive(Debug, Clone)]
 pub struct Cursor {
+    #[allow(dead_code)]
     pub clipboard: String,
     pub line: u16,
     pub col: u16,
-    pub selection: ((u16, u16), (u16, u16)),
+    /// Where a selection was started, it runs from there to the cursor
+    pub anchor: Option<(u16, u16)>,
 }
 
 impl Cursor {
@@ -12,15 +14,28 @@ impl Cursor {
             line: 0,
             col: 0,
             clipboard: String::new(),
-            selection: ((0, 0), (0, 0)),
+            anchor: None,
         }
     }
 
+    /// Move the cursor to (line, col), selecting from where it was
     pub fn expand_selection(&mut self, line: u16, col: u16) {
-        if self.selection.0 == (0, 0) {
-            self.selection.0 = (self.line, self.col);
+        if self.anchor.is_none() {
+            self.anchor = Some((self.line, self.col));
         }
-        self.selection.1 = (line, col);
+        self.line = line;
+        self.col = col;
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.anchor = None;
+    }
+
+    /// The selected (line, col) range, start first, None if nothing is selected
+    pub fn selection(&self) -> Option<((u16, u16), (u16, u16))> {
+        let anchor = self.anchor?;
+        let position = (self.line, self.col);
+        (anchor != position).then(|| (anchor.min(position), anchor.max(position)))
     }
 
 }
@@ -29,4 +44,4 @@ impl PartialEq for Cursor {
     fn eq(&self, other: &Self) -> bool {
         self.line == other.line && self.col == other.col
     }
-}
\ No newline at end of file
+}
@@ -1,11 +1,30 @@
-use crate::{get_line_len_int, get_line_len};
-use std::io::Write;
+use crate::{
+    get_line_len_int, get_line_len,
+    utils::{config::{self, Config, Settings}, diff::DiffView, encoding, format::{FileFormat, LineEnding}, swap::{Recovery, SwapFiles}, watcher::{ExternalChange, FileWatcher}}
+};
+use std::{
+    collections::HashMap,
+    path::Path
+};
+use toml::Value;
 
 use super::{
+    buffer::Buffer,
     cursor::Cursor,
     commands::Command,
+    explorer::{normalize, Explorer},
+    keymap::Keymap,
+    large::{self, LargeFile},
+    completion::PathCompleter,
     finder::Finder,
-    history::{History, HistoryEntry}
+    picker::FilePicker,
+    window::{FocusDirection, SplitDirection, View, WindowManager},
+    history::{History, HistoryEntry},
+    brackets::{self, MatchCache, Syntax},
+    comment,
+    indent::{self, Indent, Indenter},
+    language,
+    pairs
 };
 
 pub struct Editor {
@@ -13,53 +32,222 @@ pub struct Editor {
     pub lines: Vec<String>,
     pub cursors: Vec<Cursor>,
     pub filename: String,
-    pub file_to_open: String,
     pub history: History,
     pub notif_text: String,
     pub command_mode: bool,
     pub command: Command,
     pub changes_saved: bool,
-    pub finder: Finder
+    pub finder: Finder,
+    pub marks: HashMap<char, u16>,
+    /// Line endings, final newline and BOM of the active buffer's file
+    pub format: FileFormat,
+    /// Edits to the active buffer are refused
+    pub read_only: bool,
+    /// Open every file read-only (`--readonly`)
+    pub readonly_mode: bool,
+    /// The active buffer is written to stdout on exit (`--stdout`)
+    pub write_stdout: bool,
+    /// Set while the active buffer is a file too big to load at once
+    pub large: Option<LargeFile>,
+    /// Indentation guessed from the active buffer's file when it was opened
+    pub detected_indent: Indent,
+    /// Indentation set for the active buffer alone
+    pub indent: Indent,
+    /// Auto-indentation for the active buffer's filetype
+    pub indenter: Indenter,
+    pub config: Config,
+    /// Options in effect for the active buffer, from `config`
+    pub settings: Settings,
+    pub keymap: Keymap,
+    pub quit: bool,
+    pub picker: FilePicker,
+    pub recent_files: Vec<String>,
+    pub completer: PathCompleter,
+    pub scroll: usize,
+    /// Every open buffer. The entry at `active_buffer` is a placeholder,
+    /// its real state is in the fields above while it is active.
+    pub buffers: Vec<Buffer>,
+    pub active_buffer: usize,
+    pub buffer_selection: usize,
+    /// Selected row of the unsaved changes prompt shown on quit
+    pub quit_selection: usize,
+    /// Buffers the user chose not to save in the quit prompt
+    pub quit_discarded: Vec<usize>,
+    pub windows: WindowManager,
+    pub explorer: Explorer,
+    pub swap: SwapFiles,
+    /// Swap files found when opening files, waiting for the user to decide on
+    pub recoveries: Vec<Recovery>,
+    pub watcher: FileWatcher,
+    /// Dirty buffers whose file was changed by another program
+    pub external_changes: Vec<ExternalChange>,
+    /// Diff shown over the editor by `--diff`
+    pub diff_view: Option<DiffView>,
+    /// The brackets highlighted at the cursor
+    bracket_match: MatchCache,
 }
 
 impl Editor {
     pub fn new() -> Self {
         let mut temp = Self {
             text: String::new(),
-            lines: Vec::new(),
+            lines: vec![String::new()],
             cursors: Vec::new(),
             filename: String::new(),
-            file_to_open: String::new(),
             history: History::new(),
             notif_text: String::from("Editor mode"),
             command_mode: false,
             command: Command::new(),
             changes_saved: true,
-            finder: Finder::new()
+            finder: Finder::new(),
+            marks: HashMap::new(),
+            format: FileFormat::new(),
+            read_only: false,
+            readonly_mode: false,
+            write_stdout: false,
+            large: None,
+            detected_indent: Indent::new(),
+            indent: Indent::new(),
+            indenter: Indenter::new(None),
+            config: Config::new(None),
+            settings: Settings::new(),
+            keymap: Keymap::new(),
+            quit: false,
+            picker: FilePicker::new(),
+            recent_files: Vec::new(),
+            completer: PathCompleter::new(),
+            scroll: 0,
+            buffers: vec![Buffer::new()],
+            active_buffer: 0,
+            buffer_selection: 0,
+            quit_selection: 0,
+            quit_discarded: Vec::new(),
+            windows: WindowManager::new(),
+            explorer: Explorer::new(),
+            swap: SwapFiles::new(),
+            recoveries: Vec::new(),
+            watcher: FileWatcher::new(),
+            external_changes: Vec::new(),
+            diff_view: None,
+            bracket_match: MatchCache::new(),
         };
 
         temp.cursors.push(Cursor::new());
         temp
     }
 
-    pub fn dbg(&mut self, string: String) {
-        self.lines.insert(0, string);
-    }
+    /// Change an option at runtime, used by `:set`
+    ///
+    /// With no value, the current value of the option is reported
+    pub fn set_option(&mut self, option: &str, value: Option<&str>) -> Result<String, String> {
+        // options from the config file apply to every buffer, except for indentation
+        if let Some(parsed) = config::parse_set(option, value) {
+            let (name, value) = parsed?;
+            match (name, value) {
+                ("tab_width", Some(Value::Integer(width))) => self.indent.width = Some(width as usize),
+                ("hard_tabs", Some(Value::Boolean(hard_tabs))) => self.indent.hard_tabs = Some(hard_tabs),
+                (name, Some(value)) => self.config.set(name, value),
+                (_, None) => {}
+            }
+            self.apply_settings();
+            return Ok(format!("{}={}", name, self.settings.get(name)));
+        }
 
-    pub fn save(self) {
-        let file = std::fs::File::create(&self.filename);
+        match option {
+            "fileformat" | "ff" => {
+                let value = match value {
+                    Some(value) => value,
+                    None => return Ok(format!("fileformat={}", self.format.line_ending.name())),
+                };
+                match LineEnding::from_name(value) {
+                    Some(line_ending) => {
+                        if line_ending != self.format.line_ending || self.format.mixed_endings {
+                            self.changes_saved = false;
+                        }
+                        self.format.line_ending = line_ending;
+                        self.format.mixed_endings = false;
+                        Ok(format!("fileformat={}", line_ending.name()))
+                    }
+                    None => Err(format!("Invalid fileformat: {} (expected unix, dos or mac)", value)),
+                }
+            }
+            "encoding" | "enc" | "fileencoding" | "fenc" => {
+                let value = match value {
+                    Some(value) => value,
+                    None => return Ok(format!("encoding={}", self.format.encoding.name())),
+                };
+                let new_encoding = encoding::from_label(value)
+                    .ok_or_else(|| format!("Unknown encoding: {}", value))?;
 
-        let content = self.lines.join("\n");
-        match file {
-            Ok(mut file) => {
-                let write = file.write_all(content.as_bytes());
-                match write {
-                    Ok(_) => {}
-                    Err(_) => {}
+                if new_encoding != self.format.encoding {
+                    self.format.encoding = new_encoding;
+                    self.changes_saved = false;
+                }
+                // only the Unicode encodings have a byte order mark
+                if !encoding::is_unicode(new_encoding) {
+                    self.format.bom = false;
                 }
+                Ok(format!("encoding={}", new_encoding.name()))
             }
-            Err(_) => {}
+            "readonly" | "ro" | "noreadonly" | "noro" => {
+                if value.is_some() {
+                    return Err(format!("{} doesn't take a value", option));
+                }
+                self.read_only = !option.starts_with("no");
+                Ok(String::from(if self.read_only { "readonly" } else { "noreadonly" }))
+            }
+            "bomb" | "nobomb" if option == "bomb" && !encoding::is_unicode(self.format.encoding) => {
+                Err(format!("{} files can't have a BOM", self.format.encoding.name()))
+            }
+            "bomb" | "nobomb" => self.set_format_flag(option, value, "bomb", |format| &mut format.bom),
+            "eol" | "noeol" => self.set_format_flag(option, value, "eol", |format| &mut format.trailing_newline),
+            _ => Err(format!("Unknown option: {}", option)),
+        }
+    }
+
+    /// Look up the options for the active buffer's file again
+    pub fn apply_settings(&mut self) {
+        self.settings = self.config.settings(&self.filename, self.detected_indent);
+        self.indent.apply(&mut self.settings);
+        let filetype = language::filetype(&self.filename);
+        if self.indenter.filetype() != filetype {
+            self.indenter = Indenter::new(filetype);
         }
+        self.history.set_max_size(self.settings.history_size);
+    }
+
+    /// Work out the options of every buffer again, after the config changed
+    pub fn reapply_settings(&mut self) {
+        self.apply_settings();
+        for (i, buffer) in self.buffers.iter_mut().enumerate() {
+            if i != self.active_buffer {
+                buffer.settings = self.config.settings(&buffer.filename, buffer.detected_indent);
+                buffer.indent.apply(&mut buffer.settings);
+            }
+        }
+    }
+
+    /// Handle a boolean file format option, `name` sets it and `noname` clears it
+    fn set_format_flag(&mut self, option: &str, value: Option<&str>, name: &str, flag: fn(&mut FileFormat) -> &mut bool) -> Result<String, String> {
+        if value.is_some() {
+            return Err(format!("{} doesn't take a value", option));
+        }
+
+        let enabled = option == name;
+        let current = flag(&mut self.format);
+        if *current != enabled {
+            *current = enabled;
+            self.changes_saved = false;
+        }
+        Ok(option.to_string())
+    }
+
+    /// Check the active buffer may be edited, saying why not if it can't
+    pub fn editable(&mut self) -> bool {
+        if self.read_only {
+            self.notif_text = String::from("Buffer is read-only (:set noreadonly to edit)");
+        }
+        !self.read_only
     }
 
     pub fn undo(&mut self) {
@@ -69,6 +257,7 @@ impl Editor {
         if let Some(history_entry) = self.history.undo() {
             self.cursors = history_entry.cursors;
             self.lines = history_entry.lines;
+            self.changes_saved = self.history.is_saved();
         }
         else {
             self.notif_text = String::from("No edits to undo");
@@ -79,6 +268,7 @@ impl Editor {
         if let Some(history_entry) = self.history.redo() {
             self.cursors = history_entry.cursors;
             self.lines = history_entry.lines;
+            self.changes_saved = self.history.is_saved();
         }
         else {
             self.notif_text = String::from("No edits to redo");
@@ -94,6 +284,7 @@ impl Editor {
     /**
      * Get a mutable reference to the line we want to work on
      */
+    #[allow(dead_code)]
     pub fn get_line(&mut self, cursor: &mut Cursor) -> &mut String {
         &mut self.lines[cursor.line as usize]
     }
@@ -106,24 +297,22 @@ impl Editor {
         let mut edited_flag = true;
         self.push_history(Command::Backspace);
 
+        let pairs = self.pairs();
         for cursor in &mut self.cursors {
             let col = cursor.col as usize;
     
             if col > 0 {
-                if col > 4 && &self.lines[cursor.line as usize][col - 4..col] == "    " {
-                    dbg!("deleting tab");
-                    let line = &mut self.lines[cursor.line as usize];
-                    line.remove(col - 4);
-                    line.remove(col - 3);
-                    line.remove(col - 2);
-                    line.remove(col - 1);
-                    cursor.col -= 4;
-                } 
-                else {
-                    let line = &mut self.lines[cursor.line as usize];
-                    line.remove(col as usize - 1);
-                    cursor.col -= 1;
+                let line = &mut self.lines[cursor.line as usize];
+                if pairs::empty_pair(pairs, &line[..col], &line[col..]) {
+                    let open = line[..col].chars().next_back().map_or(1, char::len_utf8);
+                    let close = line[col..].chars().next().map_or(1, char::len_utf8);
+                    line.replace_range(col - open..col + close, "");
+                    cursor.col -= open as u16;
+                    continue;
                 }
+                let start = indent::backspace_start(&line[..col], self.settings.tab_width);
+                line.replace_range(start..col, "");
+                cursor.col = start as u16;
             } 
             else if cursor.line == 0 {
                 edited_flag = false;
@@ -136,7 +325,7 @@ impl Editor {
                 self.lines[cursor.line as usize - 1].push_str(&line);
                 self.lines.remove(cursor.line as usize);
                 cursor.line -= 1;
-                cursor.col = prev_len as u16;
+                cursor.col = prev_len;
             }
         }
 
@@ -175,15 +364,11 @@ impl Editor {
         }
     }
 
-    pub fn backspace_line(&mut self) {
-        self.push_history(Command::Backspace);
-
-        for cursor in &mut self.cursors {
-            if cursor.col > 0 {
-                let line = &mut self.lines[cursor.line as usize];
-                line.clear();
-                cursor.col = 0;
-            }
+    /// Brackets and quotes closed as they're typed in the active buffer
+    fn pairs(&self) -> &'static [(char, char)] {
+        match self.settings.auto_pairs {
+            true => language::pairs(self.indenter.filetype()),
+            false => &[],
         }
     }
 
@@ -194,11 +379,47 @@ impl Editor {
         };
         self.push_history(comm);
 
+        let pairs = self.pairs();
         for cursor in &mut self.cursors {
+            let row = cursor.line as usize;
             let cursor_x = cursor.col as usize;
-            let line = &mut self.lines[cursor.line as usize];
+
+            // typing a bracket or quote with text selected puts the text inside it
+            if let (Some((open, close)), Some((start, end))) = (pairs::opened_by(pairs, c), cursor.selection()) {
+                self.lines[end.0 as usize].insert(end.1 as usize, close);
+                self.lines[start.0 as usize].insert(start.1 as usize, open);
+                cursor.line = end.0;
+                cursor.col = end.1 + if start.0 == end.0 { 1 } else { 0 };
+                cursor.clear_selection();
+                continue;
+            }
+
+            let line = &mut self.lines[row];
+            if pairs::steps_over(pairs, c, &line[cursor_x..]) {
+                cursor.col += c.len_utf8() as u16;
+                continue;
+            }
+            let pair = pairs::opened_by(pairs, c).filter(|pair| pairs::closes(*pair, &line[..cursor_x], &line[cursor_x..]));
             line.insert(cursor_x, c);
-            cursor.col += 1;
+            cursor.col += c.len_utf8() as u16;
+            if let Some((_, close)) = pair {
+                line.insert(cursor.col as usize, close);
+            }
+
+            // " * " + "/" ends a comment
+            if self.indenter.closes_comment(&line[..cursor.col as usize]) {
+                line.remove(cursor_x - 1);
+                cursor.col -= 1;
+            }
+
+            // typing the `}` or `end` that closes a block moves it back
+            let line = &self.lines[row];
+            let before = line.get(..cursor.col as usize).unwrap_or(line);
+            if let Some(outer) = self.indenter.dedent(&self.lines[..row], line, before, &self.settings) {
+                let own = indent::leading(line).len();
+                self.lines[row].replace_range(..own, &outer);
+                cursor.col = (cursor.col as usize + outer.len() - own) as u16;
+            }
         }
     }
 
@@ -211,80 +432,632 @@ impl Editor {
         }
     }
 
+    /**
+     * At the start of a line, indent it as far as the lines above call for,
+     * anywhere else (or when it already is) insert one level of indentation
+     */
     pub fn tab(&mut self) {
         self.push_history(Command::Tab);
-        
+
+        let tab = self.settings.indent_unit();
         for cursor in &mut self.cursors {
+            let row = cursor.line as usize;
             let col = cursor.col as usize;
-            let mut tabs = 1;
-
-            //auto tab if previous line was tabbed in
-            if cursor.line > 1 {
-
-                //search for the last line that is not empty?
-                let prev_line = self.lines[cursor.line as usize - 1].clone();
-
-                // let mut prev_line = String::from("");
-                // let mut i = 0;
-                // while i > 1 && prev_line.len() == 0 {
-                //     prev_line = self.lines[cursor.line as usize - 1 - i].clone();
-                //     i += 1;
-                // }
-                
-                let chars: Vec<char> = prev_line.chars().collect();
-                let mut i = 0;
-
-                while i < chars.len() && chars[i] == ' ' {
-                    i += 1;
+            let line = &self.lines[row];
+
+            if indent::leading(line).len() >= col {
+                let expected = self.indenter.expected(&self.lines[..row], &self.settings);
+                let own = indent::leading(line).len();
+                if indent::display_width(&expected, self.settings.tab_width) > indent::display_width(&line[..own], self.settings.tab_width) {
+                    self.lines[row].replace_range(..own, &expected);
+                    cursor.col = expected.len() as u16;
+                    continue;
+                }
+            }
+
+            // spaces go up to the next tab stop
+            let insert = match self.settings.hard_tabs {
+                true => tab.clone(),
+                false => {
+                    let column = indent::display_width(line.get(..col).unwrap_or(line), self.settings.tab_width);
+                    " ".repeat(self.settings.tab_width - column % self.settings.tab_width)
                 }
+            };
+            self.lines[row].insert_str(col, &insert);
+            cursor.col += insert.len() as u16;
+        }
+    }
+
+    /**
+     * Remove whitespace at the end of every line, keeping the cursors on
+     * their lines. It can be undone like an edit.
+     */
+    pub fn trim_trailing_whitespace(&mut self) {
+        if !self.lines.iter().any(|line| line.ends_with(char::is_whitespace)) {
+            return;
+        }
+        self.push_history(Command::Null);
+
+        for line in &mut self.lines {
+            line.truncate(line.trim_end().len());
+        }
+        for cursor in &mut self.cursors {
+            let len = self.lines[cursor.line as usize].len() as u16;
+            cursor.col = cursor.col.min(len);
+        }
+    }
 
-                tabs += i / 4;
+    /**
+     * Comment or uncomment the lines of every cursor and selection
+     */
+    pub fn toggle_line_comment(&mut self) {
+        let rows: Vec<usize> = self.line_blocks().into_iter().flat_map(|(first, last)| first..=last).collect();
+        let comments = language::comments(self.indenter.filetype());
+        match comment::toggle_lines(&self.lines, &rows, comments) {
+            Some(edits) => self.apply_edits(edits),
+            None => self.notif_text = String::from("No comments in this file type"),
+        }
+    }
 
-                //only give the user an extra tab if the last charcter was a {
-                if chars.len() != 0 && chars[chars.len() - 1] != '{' {
-                    tabs-= 1;
+    /**
+     * Put a block comment around each selection, or the line of a cursor
+     * without one, or take it off if there is one
+     */
+    pub fn toggle_block_comment(&mut self) {
+        let comments = language::comments(self.indenter.filetype());
+        let mut edits = Vec::new();
+        for cursor in &self.cursors {
+            let position = (cursor.line as usize, cursor.col as usize);
+            let (from, to) = cursor.selection()
+                .map_or((position, position), |(start, end)| ((start.0 as usize, start.1 as usize), (end.0 as usize, end.1 as usize)));
+            match comment::toggle_block(&self.lines, from, to, comments) {
+                Some(more) => edits.extend(more),
+                None => {
+                    self.notif_text = String::from("No block comments in this file type");
+                    return;
                 }
             }
+        }
+        self.apply_edits(edits);
+    }
 
-            let line = &mut self.lines[cursor.line as usize];
+    /**
+     * Make edits within lines as one undo step, keeping the cursors and
+     * selections on the same text
+     */
+    fn apply_edits(&mut self, mut edits: Vec<comment::Edit>) {
+        if edits.is_empty() {
+            return;
+        }
+        self.push_history(Command::Null);
+        self.changes_saved = false;
 
-            for _ in 0..tabs {
-                line.insert_str(col, "    ");
-                cursor.col += 4;
+        // from the end of the buffer, so offsets of the edits still to do hold
+        edits.sort_by_key(|edit| std::cmp::Reverse((edit.line, edit.at)));
+        let shift = |line: u16, col: u16, after: bool| {
+            edits.iter()
+                .filter(|edit| edit.line == line as usize)
+                .fold(col as usize, |col, edit| edit.shift(col, after)) as u16
+        };
+        for cursor in &mut self.cursors {
+            let position = (cursor.line, cursor.col);
+            match cursor.anchor {
+                Some(anchor) => {
+                    cursor.anchor = Some((anchor.0, shift(anchor.0, anchor.1, anchor > position)));
+                    cursor.col = shift(cursor.line, cursor.col, position > anchor);
+                }
+                None => cursor.col = shift(cursor.line, cursor.col, true),
             }
         }
+        for edit in &edits {
+            edit.apply(&mut self.lines[edit.line]);
+        }
     }
 
     pub fn new_line(&mut self) {
         self.push_history(Command::AddNewLine);
-        
+
         for cursor in &mut self.cursors {
-            let cursor_x = cursor.col as usize;
-            let line = &mut self.lines[cursor.line as usize];
+            let row = cursor.line as usize;
+            let after = self.lines[row].split_off(cursor.col as usize);
+            let (indent, closing) = self.indenter.new_line(&self.lines[..row], &self.lines[row], &after, &self.settings);
 
-            //loop over the start of a line and see if we need to tab
-            let chars: Vec<char> = line.chars().collect();
-            let mut i = 0;
-            while i < chars.len() && chars[i] == ' ' {
+            // the text moved down is indented like a line of its own
+            let after = after.trim_start_matches([' ', '\t']);
+            match closing {
+                Some(outer) => {
+                    self.lines.insert(row + 1, indent.clone());
+                    self.lines.insert(row + 2, outer + after);
+                }
+                None => self.lines.insert(row + 1, indent.clone() + after),
+            }
+            cursor.line += 1;
+            cursor.col = indent.len() as u16;
+        }
+    }
+
+
+    //=================================================================================================
+    // LINE FUNCTIONS
+
+    /**
+     * The lines of every cursor and selection, as sorted (first, last) blocks
+     *
+     * A selection ending at the start of a line doesn't take that line.
+     * Blocks that overlap or touch are merged into one.
+     */
+    pub fn line_blocks(&self) -> Vec<(usize, usize)> {
+        let mut blocks: Vec<(usize, usize)> = self.cursors.iter()
+            .map(|cursor| match cursor.selection() {
+                Some((start, end)) if end.1 == 0 && end.0 > start.0 => (start.0 as usize, end.0 as usize - 1),
+                Some((start, end)) => (start.0 as usize, end.0 as usize),
+                None => (cursor.line as usize, cursor.line as usize),
+            })
+            .collect();
+        blocks.sort_unstable();
+
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+        for (first, last) in blocks {
+            match merged.last_mut() {
+                Some(block) if first <= block.1 + 1 => block.1 = block.1.max(last),
+                _ => merged.push((first, last)),
+            }
+        }
+        merged
+    }
+
+    /// Move every cursor and selection anchor to the line `map` gives for its line
+    fn map_cursor_lines(&mut self, map: impl Fn(usize) -> usize) {
+        for cursor in &mut self.cursors {
+            cursor.line = map(cursor.line as usize) as u16;
+            if let Some(anchor) = &mut cursor.anchor {
+                anchor.0 = map(anchor.0 as usize) as u16;
+            }
+        }
+    }
+
+    /// Drop cursors that ended up at the same place
+    fn dedup_cursors(&mut self) {
+        self.cursors.sort_by_key(|cursor| (cursor.line, cursor.col));
+        self.cursors.dedup();
+    }
+
+    /**
+     * Move the lines of every cursor and selection one line `up` or down
+     *
+     * Nothing moves if a block is already at the top or bottom.
+     */
+    pub fn move_lines(&mut self, up: bool) {
+        let blocks = self.line_blocks();
+        let stuck = match up {
+            true => blocks.first().is_some_and(|(first, _)| *first == 0),
+            false => blocks.last().is_some_and(|(_, last)| last + 1 >= self.lines.len()),
+        };
+        if stuck {
+            return;
+        }
+        self.push_history(Command::Null);
+        self.changes_saved = false;
+
+        // blocks never touch, so the line each one swaps with is its own
+        for (first, last) in &blocks {
+            match up {
+                true => self.lines[first - 1..=*last].rotate_left(1),
+                false => self.lines[*first..=last + 1].rotate_right(1),
+            }
+        }
+
+        // a selection ending at the start of the line after a block moves with it
+        let moved = |line: u16, col: u16, end: bool| {
+            let line = line as usize;
+            blocks.iter().any(|(first, last)| (*first..=*last).contains(&line) || (end && col == 0 && line == last + 1))
+        };
+        let step = |line: u16| if up { line - 1 } else { line + 1 };
+        for cursor in &mut self.cursors {
+            let end = cursor.selection().map(|(_, end)| end);
+            if moved(cursor.line, cursor.col, end == Some((cursor.line, cursor.col))) {
+                cursor.line = step(cursor.line);
+            }
+            if let Some(anchor) = &mut cursor.anchor {
+                if moved(anchor.0, anchor.1, end == Some(*anchor)) {
+                    anchor.0 = step(anchor.0);
+                }
+            }
+        }
+    }
+
+    /**
+     * Copy the lines of every cursor and selection below themselves,
+     * the cursors go along with the copy
+     */
+    pub fn duplicate_lines(&mut self) {
+        let blocks = self.line_blocks();
+        self.push_history(Command::Null);
+        self.changes_saved = false;
+
+        for (first, last) in blocks.iter().rev() {
+            let copy = self.lines[*first..=*last].to_vec();
+            self.lines.splice(last + 1..last + 1, copy);
+        }
+        self.map_cursor_lines(|line| {
+            let added: usize = blocks.iter()
+                .filter(|(first, _)| *first <= line)
+                .map(|(first, last)| last - first + 1)
+                .sum();
+            line + added
+        });
+    }
+
+    /**
+     * Delete the lines of every cursor and selection
+     */
+    pub fn delete_lines(&mut self) {
+        let blocks = self.line_blocks();
+        self.push_history(Command::Null);
+        self.changes_saved = false;
+
+        for (first, last) in blocks.iter().rev() {
+            self.lines.drain(*first..=*last);
+        }
+        if self.lines.is_empty() {
+            self.lines.push(String::new());
+        }
+        self.map_cursor_lines(|line| {
+            let removed: usize = blocks.iter()
+                .filter(|(_, last)| *last < line)
+                .map(|(first, last)| last - first + 1)
+                .sum();
+            let line = blocks.iter().find(|(first, last)| (*first..=*last).contains(&line)).map_or(line, |(first, _)| *first);
+            line - removed
+        });
+        self.cursors.iter_mut().for_each(Cursor::clear_selection);
+        self.adjust_cursors();
+        self.dedup_cursors();
+    }
+
+    /**
+     * Join the lines of every selection into one, or the line of a cursor
+     * with the next one
+     *
+     * The indentation of the joined lines goes and a space separates them.
+     * Cursors end up where the last two lines met.
+     */
+    pub fn join_lines(&mut self) {
+        let end = self.lines.len() - 1;
+        let blocks: Vec<(usize, usize)> = self.line_blocks().into_iter()
+            .map(|(first, last)| (first, last.max(first + 1).min(end)))
+            .filter(|(first, last)| first < last)
+            .collect();
+        if blocks.is_empty() {
+            return;
+        }
+        self.push_history(Command::Null);
+        self.changes_saved = false;
+
+        let mut joints = Vec::new();
+        for (first, last) in blocks.iter().rev() {
+            let mut joined = std::mem::take(&mut self.lines[*first]);
+            let mut joint = joined.len();
+            for line in self.lines.drain(first + 1..=*last) {
+                let text = line.trim_start();
+                joined.truncate(joined.trim_end().len());
+                joint = joined.len();
+                if !joined.is_empty() && !text.is_empty() {
+                    joined.push(' ');
+                }
+                joined.push_str(text);
+            }
+            self.lines[*first] = joined;
+            joints.push(joint);
+        }
+        joints.reverse();
+
+        for cursor in &mut self.cursors {
+            let line = cursor.line as usize;
+            let removed: usize = blocks.iter()
+                .filter(|(_, last)| *last < line)
+                .map(|(first, last)| last - first)
+                .sum();
+            match blocks.iter().position(|(first, last)| (*first..=*last).contains(&line)) {
+                Some(i) => {
+                    cursor.line = (blocks[i].0 - removed) as u16;
+                    cursor.col = joints[i] as u16;
+                }
+                None => cursor.line = (line - removed) as u16,
+            }
+            cursor.clear_selection();
+        }
+        self.dedup_cursors();
+    }
+
+    /**
+     * Indent the lines of every cursor and selection by one level,
+     * leaving empty lines alone
+     */
+    pub fn indent_lines(&mut self) {
+        let unit = self.settings.indent_unit();
+        let edits = self.line_blocks().into_iter()
+            .flat_map(|(first, last)| first..=last)
+            .filter(|row| !self.lines[*row].is_empty())
+            .map(|row| comment::Edit::insert(row, 0, unit.clone()))
+            .collect();
+        self.apply_edits(edits);
+    }
+
+    /**
+     * Take one level of indentation off the lines of every cursor and selection
+     */
+    pub fn outdent_lines(&mut self) {
+        let edits = self.line_blocks().into_iter()
+            .flat_map(|(first, last)| first..=last)
+            .filter_map(|row| {
+                let leading = indent::leading(&self.lines[row]);
+                let start = indent::backspace_start(leading, self.settings.tab_width);
+                (!leading.is_empty()).then(|| comment::Edit::remove(row, start, leading.len() - start))
+            })
+            .collect();
+        self.apply_edits(edits);
+    }
+
+
+    //=================================================================================================
+    // BUFFER FUNCTIONS
+
+    /// Move the active buffer's state out of the editor
+    fn take_buffer(&mut self) -> Buffer {
+        Buffer {
+            lines: std::mem::take(&mut self.lines),
+            cursors: std::mem::take(&mut self.cursors),
+            history: std::mem::replace(&mut self.history, History::new()),
+            filename: std::mem::take(&mut self.filename),
+            changes_saved: self.changes_saved,
+            scroll: self.scroll,
+            marks: std::mem::take(&mut self.marks),
+            format: std::mem::replace(&mut self.format, FileFormat::new()),
+            read_only: self.read_only,
+            large: self.large.take(),
+            detected_indent: std::mem::take(&mut self.detected_indent),
+            indent: std::mem::take(&mut self.indent),
+            settings: self.settings.clone(),
+        }
+    }
+
+    /// Make `buffer` the live state of the editor
+    fn load_buffer(&mut self, buffer: Buffer) {
+        self.lines = buffer.lines;
+        self.cursors = buffer.cursors;
+        self.history = buffer.history;
+        self.filename = buffer.filename;
+        self.changes_saved = buffer.changes_saved;
+        self.scroll = buffer.scroll;
+        self.marks = buffer.marks;
+        self.format = buffer.format;
+        self.read_only = buffer.read_only;
+        self.large = buffer.large;
+        self.detected_indent = buffer.detected_indent;
+        self.indent = buffer.indent;
+        self.apply_settings();
+    }
+
+    /// Indices of every buffer with unsaved changes
+    pub fn dirty_buffers(&self) -> Vec<usize> {
+        self.buffer_list().iter()
+            .enumerate()
+            .filter(|(_, (_, saved))| !saved)
+            // with --stdout the active buffer isn't lost, it goes to stdout
+            .filter(|(i, _)| !self.write_stdout || *i != self.active_buffer)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    pub fn buffer_count(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Get the (filename, changes_saved) of every buffer, in order
+    pub fn buffer_list(&self) -> Vec<(String, bool)> {
+        self.buffers.iter()
+            .enumerate()
+            .map(|(i, buffer)| {
+                if i == self.active_buffer {
+                    let name = if self.filename.is_empty() { String::from("[No Name]") } else { self.filename.clone() };
+                    (name, self.changes_saved)
+                } else {
+                    (buffer.display_name(), buffer.changes_saved)
+                }
+            })
+            .collect()
+    }
+
+    pub fn switch_buffer(&mut self, index: usize) {
+        if index == self.active_buffer || index >= self.buffers.len() {
+            return;
+        }
+
+        let current = self.take_buffer();
+        self.buffers[self.active_buffer] = current;
+        let next = std::mem::replace(&mut self.buffers[index], Buffer::new());
+        self.load_buffer(next);
+        self.active_buffer = index;
+        self.windows.focused_view_mut().buffer = index;
+    }
+
+    pub fn next_buffer(&mut self) {
+        self.switch_buffer((self.active_buffer + 1) % self.buffers.len());
+    }
+
+    pub fn prev_buffer(&mut self) {
+        self.switch_buffer((self.active_buffer + self.buffers.len() - 1) % self.buffers.len());
+    }
+
+    /// Open a new, empty buffer and switch to it
+    pub fn new_buffer(&mut self) {
+        self.buffers.push(Buffer::new());
+        self.switch_buffer(self.buffers.len() - 1);
+    }
+
+    /// Move the active buffer left (negative) or right in the buffer list
+    pub fn move_buffer(&mut self, offset: isize) {
+        let target = self.active_buffer as isize + offset;
+        if target < 0 || target as usize >= self.buffers.len() {
+            return;
+        }
+
+        self.buffers.swap(self.active_buffer, target as usize);
+        self.windows.buffers_swapped(self.active_buffer, target as usize);
+        self.active_buffer = target as usize;
+    }
+
+    /// Find the buffer that has `filename` open
+    pub fn find_buffer(&self, filename: &str) -> Option<usize> {
+        if self.filename == filename {
+            return Some(self.active_buffer);
+        }
+        self.buffers.iter().position(|buffer| buffer.filename == filename)
+    }
+
+    /// Point buffers at a file's new location after it (or a parent directory) was moved
+    pub fn rename_buffers(&mut self, from: &Path, to: &Path) {
+        let rename = |filename: &mut String| {
+            let path = normalize(Path::new(filename.as_str()));
+            if let Ok(rest) = path.strip_prefix(from) {
+                *filename = to.join(rest).to_string_lossy().to_string();
+            }
+        };
+
+        rename(&mut self.filename);
+        for buffer in &mut self.buffers {
+            rename(&mut buffer.filename);
+        }
+    }
+
+    /**
+     * Close the buffers of a file that was deleted, or of the files in a deleted directory
+     *
+     * Buffers with unsaved changes stay open without a name, so saving them
+     * asks where to instead of bringing the file back. Returns how many did.
+     */
+    pub fn buffers_deleted(&mut self, path: &Path) -> usize {
+        let deleted = |filename: &str| !filename.is_empty() && normalize(Path::new(filename)).starts_with(path);
+        let mut kept = 0;
+        let mut i = 0;
+        while i < self.buffers.len() {
+            let (filename, saved) = if i == self.active_buffer {
+                (&mut self.filename, self.changes_saved)
+            } else {
+                let buffer = &mut self.buffers[i];
+                (&mut buffer.filename, buffer.changes_saved)
+            };
+            if !deleted(filename) {
                 i += 1;
+                continue;
+            }
+            if !saved {
+                filename.clear();
+                kept += 1;
+                i += 1;
+                continue;
             }
 
-            //extra space of the last character is a {
-            if chars.len() > 0 && chars[chars.len() - 1] == '{' {
-                i += 4;
+            // the last buffer is emptied instead of closed
+            if self.buffers.len() == 1 {
+                self.close_buffer();
+                break;
             }
-            let tabs = i as u16 / 4;
+            let previous = self.active_buffer;
+            self.switch_buffer(i);
+            self.close_buffer();
+            if previous != i {
+                self.switch_buffer(if previous > i { previous - 1 } else { previous });
+            }
+        }
+        kept
+    }
+
+    /// True if the active buffer is an untouched, unnamed scratch buffer
+    pub fn is_scratch(&self) -> bool {
+        self.filename.is_empty() && self.changes_saved && self.lines.len() <= 1 && self.lines[0].is_empty()
+    }
+
+    /// Close the active buffer without checking for unsaved changes
+    ///
+    /// The last buffer is replaced with an empty one instead of being closed
+    pub fn close_buffer(&mut self) {
+        if self.buffers.len() == 1 {
+            self.load_buffer(Buffer::new());
+            return;
+        }
+
+        let closing = self.active_buffer;
+        let next = if closing + 1 < self.buffers.len() { closing + 1 } else { closing - 1 };
+        self.switch_buffer(next);
+        self.buffers.remove(closing);
+        if self.active_buffer > closing {
+            self.active_buffer -= 1;
+        }
+        self.windows.buffer_removed(closing, self.active_buffer);
+    }
+
+    //=================================================================================================
+    // WINDOW FUNCTIONS
+
+    /// Move focus to another view, loading its buffer, cursors and scroll
+    pub fn focus_view(&mut self, id: usize) {
+        if id == self.windows.focused || !self.windows.views.contains_key(&id) {
+            return;
+        }
+
+        // park the focused view's state before leaving it
+        let cursors = self.cursors.clone();
+        let scroll = self.scroll;
+        let view = self.windows.focused_view_mut();
+        view.cursors = cursors;
+        view.scroll = scroll;
 
-            let new_line = &mut line.split_off(cursor_x);
-            for _ in 0..tabs {
-                new_line.push_str("    ");
+        self.windows.focused = id;
+        let view = self.windows.views[&id].clone();
+        self.switch_buffer(view.buffer);
+        self.cursors = view.cursors;
+        self.scroll = view.scroll;
+        self.adjust_cursors();
+    }
+
+    /// Split the focused view, the new view shows the same buffer and gets focus
+    pub fn split_window(&mut self, direction: SplitDirection) {
+        let view = View {
+            buffer: self.active_buffer,
+            cursors: self.cursors.clone(),
+            scroll: self.scroll,
+        };
+        let id = self.windows.split(direction, view);
+        self.focus_view(id);
+    }
+
+    pub fn close_window(&mut self) -> bool {
+        match self.windows.close() {
+            Some(next) => {
+                // the closed view is gone, so there is nothing to park
+                self.windows.focused = next;
+                let view = self.windows.views[&next].clone();
+                self.switch_buffer(view.buffer);
+                self.cursors = view.cursors;
+                self.scroll = view.scroll;
+                self.adjust_cursors();
+                true
             }
-            self.lines.insert(cursor.line as usize + 1, new_line.to_owned());
-            cursor.line += 1;
-            cursor.col = tabs * 4;
+            None => false,
         }
     }
 
+    pub fn focus_next_window(&mut self) {
+        self.focus_view(self.windows.next());
+    }
+
+    pub fn focus_window(&mut self, direction: FocusDirection) {
+        if let Some(id) = self.windows.neighbour(direction) {
+            self.focus_view(id);
+        }
+    }
 
     //=================================================================================================
     // CURSOR MOVING FUNCTIONS
@@ -300,6 +1073,75 @@ impl Editor {
             }
         }
     }
+    /// Put a single cursor at (line, col), 0-based, clamped to the buffer
+    pub fn goto(&mut self, line: usize, col: usize) {
+        if self.large.is_some() {
+            large::goto(self, line, col);
+            return;
+        }
+
+        let line = line.min(self.lines.len() - 1).min(u16::MAX as usize);
+        self.cursors.truncate(1);
+        self.cursors[0].line = line as u16;
+        self.cursors[0].col = col.min(self.lines[line].len()) as u16;
+    }
+
+    /// The bracket at or before the first cursor and the one matching it
+    pub fn matching_bracket(&mut self) -> Option<((usize, usize), (usize, usize))> {
+        let (line, col) = (self.cursors[0].line as usize, self.cursors[0].col as usize);
+        self.bracket_match.matching(&self.lines, self.indenter.filetype(), line, col)
+    }
+
+    /**
+     * Move the cursor to the bracket matching the one at or before it
+     */
+    pub fn jump_to_match(&mut self) {
+        match self.matching_bracket() {
+            Some((_, (line, col))) => {
+                self.cursors.truncate(1);
+                self.cursors[0].line = line as u16;
+                self.cursors[0].col = col as u16;
+            }
+            None => self.notif_text = String::from("No matching bracket"),
+        }
+    }
+
+    /**
+     * Select inside the brackets around the cursor, or `around` them too
+     *
+     * Doing it again on what it selected selects the brackets as well, and
+     * then the next pair out, so it can be repeated to grow the selection.
+     */
+    pub fn select_brackets(&mut self, around: bool) {
+        let cursor = &self.cursors[0];
+        let position = (cursor.line as usize, cursor.col as usize);
+        let (from, to) = cursor.selection()
+            .map_or((position, position), |(start, end)| ((start.0 as usize, start.1 as usize), (end.0 as usize, end.1 as usize)));
+
+        let syntax = Syntax::new(self.indenter.filetype());
+        let mut outer = (from, to);
+        let range = loop {
+            let Some((open, close)) = brackets::enclosing(&self.lines, &syntax, outer.0, outer.1) else {
+                self.notif_text = String::from("Not inside brackets");
+                return;
+            };
+            let inside = ((open.0, open.1 + 1), close);
+            let whole = (open, (close.0, close.1 + 1));
+            if !around && inside != (from, to) {
+                break inside;
+            }
+            if whole != (from, to) {
+                break whole;
+            }
+            outer = whole;
+        };
+
+        self.cursors.truncate(1);
+        let cursor = &mut self.cursors[0];
+        cursor.anchor = Some((range.0.0 as u16, range.0.1 as u16));
+        cursor.line = range.1.0 as u16;
+        cursor.col = range.1.1 as u16;
+    }
     pub fn right(&mut self) {
         for cursor in &mut self.cursors {
             if cursor.col < get_line_len!(self, cursor) {
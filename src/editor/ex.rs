@@ -0,0 +1,991 @@
+use encoding_rs::UTF_8;
+use regex::RegexBuilder;
+use std::{cmp::Ordering, fmt};
+
+use super::{
+    commands::Command,
+    editor::Editor,
+    indent,
+    input::request_open,
+    window::SplitDirection,
+};
+use crate::utils::{
+    encoding,
+    files::{open_file, open_file_with_encoding, save_file, save_file_as}
+};
+
+///
+/// ### A single line address in an ex command
+///
+/// Lines are 1-based, like they are shown in the gutter.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Address {
+    Line(usize),
+    Current,
+    Last,
+    Mark(char),
+    Offset(Box<Address>, i64),
+}
+
+///
+/// ### The range of lines a command applies to
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum LineRange {
+    /// No range was given, the command picks its own default
+    Default,
+    /// `%`, every line in the file
+    Whole,
+    Single(Address),
+    Span(Address, Address),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExCommand {
+    Goto(Address),
+    Write { path: Option<String>, force: bool },
+    WriteQuit,
+    Edit { path: Option<String>, force: bool },
+    /// Read the current file again, decoded as `encoding`
+    Reopen { encoding: String, force: bool },
+    Quit { force: bool },
+    Substitute {
+        range: LineRange,
+        pattern: String,
+        replacement: String,
+        global: bool,
+        ignore_case: bool,
+    },
+    Set { option: String, value: Option<String> },
+    /// Sort lines, by the first number in them when `numeric`
+    Sort {
+        range: LineRange,
+        reverse: bool,
+        numeric: bool,
+        ignore_case: bool,
+        unique: bool,
+    },
+    Reverse { range: LineRange },
+    /// Rewrite indentation in the buffer's style, `width` also changes its indent width
+    Retab { range: LineRange, width: Option<usize> },
+    Mark { range: LineRange, name: char },
+    Buffers,
+    Buffer(usize),
+    BufferNext,
+    BufferPrev,
+    BufferDelete { force: bool },
+    Split { direction: SplitDirection, path: Option<String> },
+    Close,
+    Only,
+}
+
+///
+/// ### Error produced while parsing a command line
+///
+/// `position` is the byte offset into the command (without the leading `:`)
+/// where the parser gave up, so the prompt can point at it.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (col {})", self.message, self.position + 1)
+    }
+}
+
+/// Parse a full command line, e.g. `10,$s/foo/bar/g` or `w notes.txt`
+///
+/// The grammar is roughly:
+/// ```text
+/// command := range? name bang? args
+/// range   := '%' | address (',' address)?
+/// address := base offset* | offset+
+/// base    := number | '.' | '$' | '\'' mark
+/// offset  := ('+' | '-') number?
+/// ```
+pub fn parse(input: &str) -> Result<ExCommand, ParseError> {
+    Parser::new(input).parse()
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            chars: input.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, ParseError> {
+        Err(ParseError {
+            message: message.into(),
+            position: self.byte_pos(),
+        })
+    }
+
+    fn byte_pos(&self) -> usize {
+        self.chars[..self.pos].iter().map(|c| c.len_utf8()).sum()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|c| c == ' ' || c == '\t') {
+            self.pos += 1;
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.byte_pos()..]
+    }
+
+    fn parse(&mut self) -> Result<ExCommand, ParseError> {
+        self.skip_spaces();
+        if self.peek().is_none() {
+            return self.error("Empty command");
+        }
+
+        let range = self.parse_range()?;
+        self.skip_spaces();
+
+        let name_start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        let mut name: String = self.chars[name_start..self.pos].iter().collect();
+        // like in vim the mark name can follow :k right away, as in :ka
+        if name.len() > 1 && name.starts_with('k') {
+            self.pos = name_start + 1;
+            name.truncate(1);
+        }
+
+        if name.is_empty() {
+            if self.peek().is_some() {
+                return self.error(format!("Unexpected character '{}'", self.peek().unwrap()));
+            }
+            return match range {
+                LineRange::Single(address) => Ok(ExCommand::Goto(address)),
+                LineRange::Span(_, end) => Ok(ExCommand::Goto(end)),
+                LineRange::Whole => Ok(ExCommand::Goto(Address::Last)),
+                LineRange::Default => self.error("Empty command"),
+            };
+        }
+
+        let bang = self.eat('!');
+
+        match name.as_str() {
+            "w" | "write" => {
+                self.no_range(&range, &name, name_start)?;
+                let path = self.parse_path();
+                Ok(ExCommand::Write { path, force: bang })
+            }
+            "wq" | "x" | "xit" => {
+                self.no_range(&range, &name, name_start)?;
+                self.expect_end()?;
+                Ok(ExCommand::WriteQuit)
+            }
+            "q" | "quit" => {
+                self.no_range(&range, &name, name_start)?;
+                self.expect_end()?;
+                Ok(ExCommand::Quit { force: bang })
+            }
+            "e" | "edit" => {
+                self.no_range(&range, &name, name_start)?;
+                let path = self.parse_path();
+                Ok(ExCommand::Edit { path, force: bang })
+            }
+            "reo" | "reopen" => {
+                self.no_range(&range, &name, name_start)?;
+                match self.parse_path() {
+                    Some(encoding) => Ok(ExCommand::Reopen { encoding, force: bang }),
+                    None => self.error("Argument required: encoding"),
+                }
+            }
+            "ls" | "buffers" => {
+                self.no_range(&range, &name, name_start)?;
+                self.expect_end()?;
+                Ok(ExCommand::Buffers)
+            }
+            "b" | "buffer" => {
+                self.no_range(&range, &name, name_start)?;
+                self.skip_spaces();
+                if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    return self.error("Argument required: buffer number");
+                }
+                let number = self.parse_number()?;
+                self.expect_end()?;
+                Ok(ExCommand::Buffer(number))
+            }
+            "bn" | "bnext" => {
+                self.no_range(&range, &name, name_start)?;
+                self.expect_end()?;
+                Ok(ExCommand::BufferNext)
+            }
+            "bp" | "bprevious" | "bN" | "bNext" => {
+                self.no_range(&range, &name, name_start)?;
+                self.expect_end()?;
+                Ok(ExCommand::BufferPrev)
+            }
+            "sp" | "split" | "vs" | "vsplit" => {
+                self.no_range(&range, &name, name_start)?;
+                let direction = if name.starts_with('v') { SplitDirection::Vertical } else { SplitDirection::Horizontal };
+                let path = self.parse_path();
+                Ok(ExCommand::Split { direction, path })
+            }
+            "clo" | "close" => {
+                self.no_range(&range, &name, name_start)?;
+                self.expect_end()?;
+                Ok(ExCommand::Close)
+            }
+            "on" | "only" => {
+                self.no_range(&range, &name, name_start)?;
+                self.expect_end()?;
+                Ok(ExCommand::Only)
+            }
+            "bd" | "bdelete" => {
+                self.no_range(&range, &name, name_start)?;
+                self.expect_end()?;
+                Ok(ExCommand::BufferDelete { force: bang })
+            }
+            "s" | "substitute" => self.parse_substitute(range),
+            "set" | "se" => {
+                self.no_range(&range, &name, name_start)?;
+                self.parse_set()
+            }
+            "sort" | "sor" => {
+                let (mut numeric, mut ignore_case, mut unique) = (false, false, false);
+                self.skip_spaces();
+                while let Some(c) = self.peek() {
+                    match c {
+                        'n' => numeric = true,
+                        'i' => ignore_case = true,
+                        'u' => unique = true,
+                        ' ' | '\t' => {}
+                        _ => return self.error(format!("Invalid sort option '{}' (use n, i or u)", c)),
+                    }
+                    self.pos += 1;
+                }
+                Ok(ExCommand::Sort { range, reverse: bang, numeric, ignore_case, unique })
+            }
+            "rev" | "reverse" => {
+                self.expect_end()?;
+                Ok(ExCommand::Reverse { range })
+            }
+            "ret" | "retab" => {
+                self.skip_spaces();
+                let width = match self.peek() {
+                    Some(c) if c.is_ascii_digit() => Some(self.parse_number()?),
+                    _ => None,
+                };
+                self.expect_end()?;
+                Ok(ExCommand::Retab { range, width })
+            }
+            "k" | "mark" | "ma" => {
+                self.skip_spaces();
+                let name = match self.bump() {
+                    Some(c) if c.is_ascii_lowercase() => c,
+                    Some(c) => {
+                        self.pos -= 1;
+                        return self.error(format!("Invalid mark name '{}' (use a-z)", c));
+                    }
+                    None => return self.error("Argument required: mark name"),
+                };
+                self.expect_end()?;
+                Ok(ExCommand::Mark { range, name })
+            }
+            _ => {
+                self.pos = name_start;
+                self.error(format!("Not an editor command: {}", name))
+            }
+        }
+    }
+
+    fn no_range(&mut self, range: &LineRange, name: &str, name_start: usize) -> Result<(), ParseError> {
+        if *range != LineRange::Default {
+            self.pos = name_start;
+            return self.error(format!("'{}' does not take a range", name));
+        }
+        Ok(())
+    }
+
+    fn expect_end(&mut self) -> Result<(), ParseError> {
+        self.skip_spaces();
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => self.error(format!("Trailing characters: {}", self.rest())),
+        }
+    }
+
+    fn parse_range(&mut self) -> Result<LineRange, ParseError> {
+        if self.eat('%') {
+            return Ok(LineRange::Whole);
+        }
+
+        let start = match self.parse_address()? {
+            Some(address) => address,
+            None => {
+                if self.peek() == Some(',') {
+                    return self.error("Missing start of range");
+                }
+                return Ok(LineRange::Default);
+            }
+        };
+
+        self.skip_spaces();
+        if !self.eat(',') {
+            return Ok(LineRange::Single(start));
+        }
+        self.skip_spaces();
+
+        match self.parse_address()? {
+            Some(end) => Ok(LineRange::Span(start, end)),
+            None => self.error("Missing end of range"),
+        }
+    }
+
+    fn parse_address(&mut self) -> Result<Option<Address>, ParseError> {
+        let mut address = match self.peek() {
+            Some(c) if c.is_ascii_digit() => Address::Line(self.parse_number()?),
+            Some('.') => {
+                self.pos += 1;
+                Address::Current
+            }
+            Some('$') => {
+                self.pos += 1;
+                Address::Last
+            }
+            Some('\'') => {
+                self.pos += 1;
+                match self.bump() {
+                    Some(c) if c.is_ascii_lowercase() => Address::Mark(c),
+                    Some(c) => {
+                        self.pos -= 1;
+                        return self.error(format!("Invalid mark name '{}'", c));
+                    }
+                    None => return self.error("Missing mark name after '"),
+                }
+            }
+            // a bare offset like `+3` is relative to the current line
+            Some('+') | Some('-') => Address::Current,
+            _ => return Ok(None),
+        };
+
+        while let Some(sign) = self.peek().filter(|c| *c == '+' || *c == '-') {
+            self.pos += 1;
+            let amount = if self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.parse_number()? as i64
+            } else {
+                1
+            };
+            let amount = if sign == '-' { -amount } else { amount };
+            address = Address::Offset(Box::new(address), amount);
+        }
+
+        Ok(Some(address))
+    }
+
+    fn parse_number(&mut self) -> Result<usize, ParseError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        match digits.parse() {
+            Ok(n) => Ok(n),
+            Err(_) => {
+                self.pos = start;
+                self.error("Number too large")
+            }
+        }
+    }
+
+    fn parse_path(&mut self) -> Option<String> {
+        self.skip_spaces();
+        let path = self.rest().trim_end();
+        self.pos = self.chars.len();
+        if path.is_empty() {
+            None
+        } else {
+            Some(path.to_string())
+        }
+    }
+
+    fn parse_set(&mut self) -> Result<ExCommand, ParseError> {
+        self.skip_spaces();
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') {
+            self.pos += 1;
+        }
+        let option: String = self.chars[start..self.pos].iter().collect();
+        if option.is_empty() {
+            return self.error("Argument required: option name");
+        }
+
+        let value = if self.eat('=') {
+            let value = self.rest().trim().to_string();
+            if value.is_empty() {
+                return self.error(format!("Missing value for '{}'", option));
+            }
+            self.pos = self.chars.len();
+            Some(value)
+        } else {
+            None
+        };
+
+        self.expect_end()?;
+        Ok(ExCommand::Set { option, value })
+    }
+
+    fn parse_substitute(&mut self, range: LineRange) -> Result<ExCommand, ParseError> {
+        let delimiter = match self.bump() {
+            Some(c) if !c.is_alphanumeric() && c != ' ' && c != '\\' => c,
+            Some(_) => {
+                self.pos -= 1;
+                return self.error("Expected a delimiter like '/' after 's'");
+            }
+            None => return self.error("Expected /pattern/replacement/ after 's'"),
+        };
+
+        let pattern = self.parse_delimited(delimiter);
+        if pattern.is_empty() {
+            return self.error("Empty search pattern");
+        }
+        if !self.eat(delimiter) {
+            return self.error(format!("Missing '{}' after pattern", delimiter));
+        }
+
+        let replacement = self.parse_delimited(delimiter);
+        self.eat(delimiter);
+
+        let mut global = false;
+        let mut ignore_case = false;
+        while let Some(flag) = self.peek() {
+            match flag {
+                'g' => global = true,
+                'i' => ignore_case = true,
+                ' ' => {}
+                _ => return self.error(format!("Unknown flag '{}'", flag)),
+            }
+            self.pos += 1;
+        }
+
+        Ok(ExCommand::Substitute { range, pattern, replacement, global, ignore_case })
+    }
+
+    /// Read up to (not including) the next unescaped delimiter
+    fn parse_delimited(&mut self, delimiter: char) -> String {
+        let mut out = String::new();
+        while let Some(c) = self.peek() {
+            if c == delimiter {
+                break;
+            }
+            self.pos += 1;
+            if c == '\\' && self.peek() == Some(delimiter) {
+                out.push(delimiter);
+                self.pos += 1;
+                continue;
+            }
+            out.push(c);
+        }
+        out
+    }
+}
+
+/// Run a parsed command against the editor
+///
+/// Returns the text to show in the notification area, or an error message.
+pub fn execute(editor: &mut Editor, command: ExCommand) -> Result<String, String> {
+    match command {
+        ExCommand::Goto(address) => {
+            let line = resolve(editor, &address)?;
+            editor.goto(line, 0);
+            // a large file whose lines can't be read anymore stays put
+            if window_start(editor) + editor.cursors[0].line as usize != line {
+                return Err(editor.notif_text.clone());
+            }
+            Ok(format!("Line {}", line + 1))
+        }
+        ExCommand::Write { path, force } => {
+            let path = match path {
+                Some(path) => path,
+                None if editor.filename.is_empty() => return Err(String::from("No file name")),
+                None => editor.filename.clone(),
+            };
+            // writing a copy somewhere else is fine
+            if editor.read_only && !force && path == editor.filename {
+                return Err(String::from("Buffer is read-only (add ! to override)"));
+            }
+            if editor.filename.is_empty() {
+                editor.filename = path.clone();
+            }
+
+            save_file_as(editor, &path).map_err(|e| format!("Can't write \"{}\": {}", path, e))?;
+            if path == editor.filename {
+                editor.changes_saved = true;
+                editor.format.mixed_endings = false;
+            }
+            Ok(format!("\"{}\" {}L written", path, editor.lines.len()))
+        }
+        ExCommand::WriteQuit => {
+            if editor.filename.is_empty() {
+                return Err(String::from("No file name"));
+            }
+            if editor.read_only {
+                return Err(String::from("Buffer is read-only (use :q to quit)"));
+            }
+            save_file(editor).map_err(|e| format!("Can't write \"{}\": {}", editor.filename, e))?;
+            editor.changes_saved = true;
+            editor.quit = true;
+            Ok(String::from("File saved"))
+        }
+        ExCommand::Quit { force } => {
+            // with --stdout the active buffer goes to stdout, see dirty_buffers
+            if !force && !editor.changes_saved && !editor.write_stdout {
+                return Err(String::from("No write since last change (add ! to override)"));
+            }
+            let dirty = editor.dirty_buffers().len();
+            if !force && dirty > 0 {
+                return Err(format!("{} other buffer(s) have unsaved changes (add ! to override)", dirty));
+            }
+            editor.quit = true;
+            Ok(String::new())
+        }
+        ExCommand::Edit { path: Some(path), force } => {
+            // with ! a buffer that has the file already reads it again, dropping its changes
+            if let Some(index) = editor.find_buffer(&path).filter(|_| force) {
+                editor.switch_buffer(index);
+                open_file(editor);
+                return Ok(editor.notif_text.clone());
+            }
+            request_open(editor, path);
+            Ok(editor.notif_text.clone())
+        }
+        ExCommand::Edit { path: None, force } => {
+            // reload the current file from disk
+            if editor.filename.is_empty() {
+                return Err(String::from("No file name"));
+            }
+            // with --stdout the active buffer goes to stdout, see dirty_buffers
+            if !force && !editor.changes_saved && !editor.write_stdout {
+                return Err(String::from("No write since last change (add ! to override)"));
+            }
+            open_file(editor);
+            Ok(editor.notif_text.clone())
+        }
+        ExCommand::Reopen { encoding: label, force } => {
+            let forced = encoding::from_label(&label)
+                .ok_or_else(|| format!("Unknown encoding: {}", label))?;
+            if editor.filename.is_empty() {
+                return Err(String::from("No file name"));
+            }
+            if editor.large.is_some() && forced != UTF_8 {
+                return Err(String::from("Large files can only be read as UTF-8"));
+            }
+            // with --stdout the active buffer goes to stdout, see dirty_buffers
+            if !force && !editor.changes_saved && !editor.write_stdout {
+                return Err(String::from("No write since last change (add ! to override)"));
+            }
+            open_file_with_encoding(editor, Some(forced));
+            if editor.notif_text != "Edit mode" {
+                return Ok(editor.notif_text.clone());
+            }
+            Ok(format!("Reopened as {}", forced.name()))
+        }
+        ExCommand::Buffers => {
+            let list: Vec<String> = editor.buffer_list().iter()
+                .enumerate()
+                .map(|(i, (name, saved))| {
+                    let marker = if i == editor.active_buffer { "%" } else { "" };
+                    let dirty = if *saved { "" } else { "+" };
+                    format!("{}{}{} {}", i + 1, marker, dirty, name)
+                })
+                .collect();
+            Ok(list.join(" | "))
+        }
+        ExCommand::Buffer(number) => {
+            if number == 0 || number > editor.buffer_count() {
+                return Err(format!("Buffer {} does not exist", number));
+            }
+            editor.switch_buffer(number - 1);
+            Ok(format!("Buffer {} of {}", number, editor.buffer_count()))
+        }
+        ExCommand::BufferNext => {
+            editor.next_buffer();
+            Ok(format!("Buffer {} of {}", editor.active_buffer + 1, editor.buffer_count()))
+        }
+        ExCommand::BufferPrev => {
+            editor.prev_buffer();
+            Ok(format!("Buffer {} of {}", editor.active_buffer + 1, editor.buffer_count()))
+        }
+        ExCommand::Split { direction, path } => {
+            editor.split_window(direction);
+            if let Some(path) = path {
+                request_open(editor, path);
+            }
+            Ok(String::from("Split window"))
+        }
+        ExCommand::Close => {
+            if !editor.close_window() {
+                return Err(String::from("Cannot close last window"));
+            }
+            Ok(String::from("Window closed"))
+        }
+        ExCommand::Only => {
+            editor.windows.only();
+            Ok(String::from("Closed other windows"))
+        }
+        ExCommand::BufferDelete { force } => {
+            // with --stdout the active buffer goes to stdout, see dirty_buffers
+            if !force && !editor.changes_saved && !editor.write_stdout {
+                return Err(String::from("No write since last change (add ! to override)"));
+            }
+            editor.close_buffer();
+            Ok(String::from("Buffer closed"))
+        }
+        ExCommand::Substitute { .. } | ExCommand::Sort { .. } | ExCommand::Reverse { .. } | ExCommand::Retab { .. } if editor.read_only => {
+            Err(String::from("Buffer is read-only (:set noreadonly to edit)"))
+        }
+        // only a window of a large file is loaded, these would miss the rest
+        ExCommand::Substitute { .. } | ExCommand::Sort { .. } | ExCommand::Reverse { .. } | ExCommand::Retab { .. } if editor.large.is_some() => {
+            Err(String::from("Not available in large file mode"))
+        }
+        ExCommand::Substitute { range, pattern, replacement, global, ignore_case } => {
+            let (start, end) = resolve_range(editor, &range, RangeDefault::CurrentLine)?;
+            let regex = RegexBuilder::new(&pattern)
+                .case_insensitive(ignore_case)
+                .build()
+                .map_err(|e| format!("Invalid pattern: {}", e.to_string().lines().last().unwrap_or("")))?;
+            let replacement = translate_replacement(&replacement);
+
+            editor.push_history(Command::Ex);
+            let mut count = 0;
+            let (mut row, mut end) = (start, end);
+            while row <= end {
+                let line = &editor.lines[row];
+                if !regex.is_match(line) {
+                    row += 1;
+                    continue;
+                }
+                count += 1;
+                let text = if global {
+                    regex.replace_all(line, replacement.as_str()).into_owned()
+                } else {
+                    regex.replace(line, replacement.as_str()).into_owned()
+                };
+                // a \n in the replacement splits the line
+                let pieces: Vec<String> = text.split('\n').map(String::from).collect();
+                let added = pieces.len() - 1;
+                editor.lines.splice(row..=row, pieces);
+                row += added + 1;
+                end += added;
+            }
+
+            if count == 0 {
+                editor.history.pop_back();
+                return Err(format!("Pattern not found: {}", pattern));
+            }
+            editor.changes_saved = false;
+            Ok(format!("{} line(s) changed", count))
+        }
+        ExCommand::Set { option, value } => editor.set_option(&option, value.as_deref()),
+        ExCommand::Sort { range, reverse, numeric, ignore_case, unique } => {
+            let blocks = resolve_blocks(editor, &range)?;
+            editor.push_history(Command::Ex);
+
+            let (mut sorted, mut removed) = (0, 0);
+            for (start, end) in blocks.into_iter().rev() {
+                let lines = sort_lines(&editor.lines[start..=end], reverse, numeric, ignore_case, unique);
+                sorted += end - start + 1;
+                removed += end - start + 1 - lines.len();
+                editor.lines.splice(start..=end, lines);
+            }
+            editor.adjust_cursors();
+            editor.changes_saved = false;
+            match removed {
+                0 => Ok(format!("{} line(s) sorted", sorted)),
+                _ => Ok(format!("{} line(s) sorted, {} duplicate(s) removed", sorted, removed)),
+            }
+        }
+        ExCommand::Reverse { range } => {
+            let blocks = resolve_blocks(editor, &range)?;
+            editor.push_history(Command::Ex);
+
+            let mut count = 0;
+            for (start, end) in blocks {
+                editor.lines[start..=end].reverse();
+                count += end - start + 1;
+            }
+            editor.changes_saved = false;
+            Ok(format!("{} line(s) reversed", count))
+        }
+        ExCommand::Retab { range, width } => {
+            let (start, end) = resolve_range(editor, &range, RangeDefault::WholeFile)?;
+            let from = editor.settings.tab_width;
+            if let Some(width) = width {
+                if !(1..=16).contains(&width) {
+                    return Err(String::from("Indent width must be from 1 to 16"));
+                }
+                editor.indent.width = Some(width);
+                editor.apply_settings();
+            }
+
+            editor.push_history(Command::Ex);
+            let mut count = 0;
+            for line in &mut editor.lines[start..=end] {
+                if let Some(reindented) = indent::retab(line, from, &editor.settings) {
+                    *line = reindented;
+                    count += 1;
+                }
+            }
+
+            if count == 0 {
+                editor.history.pop_back();
+            } else {
+                editor.changes_saved = false;
+            }
+            Ok(format!("{} line(s) reindented with {}", count, indent::describe(&editor.settings)))
+        }
+        ExCommand::Mark { range, name } => {
+            let (_, end) = resolve_range(editor, &range, RangeDefault::CurrentLine)?;
+            // marks are kept on lines of the loaded window
+            let line = end.checked_sub(window_start(editor))
+                .filter(|line| *line < editor.lines.len())
+                .ok_or_else(|| format!("Line {} is not loaded", end + 1))?;
+            editor.marks.insert(name, line as u16);
+            Ok(format!("Mark '{}' set on line {}", name, end + 1))
+        }
+    }
+}
+
+enum RangeDefault {
+    CurrentLine,
+    WholeFile,
+}
+
+/// Resolve a range to 0-based, inclusive document lines
+fn resolve_range(editor: &Editor, range: &LineRange, default: RangeDefault) -> Result<(usize, usize), String> {
+    let last = line_count(editor).saturating_sub(1);
+    let (start, end) = match range {
+        LineRange::Default => match default {
+            RangeDefault::CurrentLine => {
+                let line = window_start(editor) + editor.cursors[0].line as usize;
+                (line, line)
+            }
+            RangeDefault::WholeFile => (0, last),
+        },
+        LineRange::Whole => (0, last),
+        LineRange::Single(address) => {
+            let line = resolve(editor, address)?;
+            (line, line)
+        }
+        LineRange::Span(start, end) => (resolve(editor, start)?, resolve(editor, end)?),
+    };
+
+    if start > end {
+        return Err(String::from("Backwards range given"));
+    }
+    Ok((start, end))
+}
+
+/// The (first, last) line blocks a command works on: `range` if one was
+/// given, else the lines of the selections, else the whole file
+fn resolve_blocks(editor: &Editor, range: &LineRange) -> Result<Vec<(usize, usize)>, String> {
+    if *range == LineRange::Default && editor.cursors.iter().any(|cursor| cursor.selection().is_some()) {
+        return Ok(editor.line_blocks());
+    }
+    resolve_range(editor, range, RangeDefault::WholeFile).map(|range| vec![range])
+}
+
+/**
+ * `lines` sorted for `:sort`
+ *
+ * Numeric sorting goes by the first integer in each line, lines without one
+ * come first. `unique` keeps one of the lines that compare equal.
+ */
+fn sort_lines(lines: &[String], reverse: bool, numeric: bool, ignore_case: bool, unique: bool) -> Vec<String> {
+    let number = |line: &str| {
+        let start = line.find(|c: char| c.is_ascii_digit())?;
+        let end = line[start..].find(|c: char| !c.is_ascii_digit()).map_or(line.len(), |len| start + len);
+        let negative = line[..start].ends_with('-');
+        line[start..end].parse::<i128>().ok().map(|n| if negative { -n } else { n })
+    };
+    let compare = |a: &String, b: &String| match (numeric, ignore_case) {
+        (true, _) => number(a).cmp(&number(b)),
+        (false, true) => a.to_lowercase().cmp(&b.to_lowercase()),
+        (false, false) => a.cmp(b),
+    };
+
+    let mut sorted = lines.to_vec();
+    sorted.sort_by(compare);
+    if unique {
+        sorted.dedup_by(|a, b| compare(a, b) == Ordering::Equal);
+    }
+    if reverse {
+        sorted.reverse();
+    }
+    sorted
+}
+
+/// Lines in the document, which is more than is loaded for a large file
+fn line_count(editor: &Editor) -> usize {
+    editor.large.as_ref().map_or(editor.lines.len(), |large| large.total_lines(editor.lines.len()))
+}
+
+/// Document line of `editor.lines[0]`
+fn window_start(editor: &Editor) -> usize {
+    editor.large.as_ref().map_or(0, |large| large.window_start)
+}
+
+/// Resolve an address to a 0-based document line
+fn resolve(editor: &Editor, address: &Address) -> Result<usize, String> {
+    let line_count = line_count(editor) as i64;
+    let start = window_start(editor) as i64;
+    let line: i64 = match address {
+        Address::Line(0) => 1,
+        Address::Line(n) => *n as i64,
+        Address::Current => start + editor.cursors[0].line as i64 + 1,
+        Address::Last => line_count,
+        Address::Mark(name) => match editor.marks.get(name) {
+            Some(line) => start + *line as i64 + 1,
+            None => return Err(format!("Mark not set: '{}", name)),
+        },
+        Address::Offset(base, amount) => resolve(editor, base)? as i64 + 1 + amount,
+    };
+
+    if line < 1 || line > line_count {
+        return Err(format!("Invalid range: line {} does not exist", line));
+    }
+    Ok(line as usize - 1)
+}
+
+/// Convert vim style replacement text (`\1`, `&`) into the regex crate's syntax
+fn translate_replacement(replacement: &str) -> String {
+    let mut out = String::new();
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '$' => out.push_str("$$"),
+            '&' => out.push_str("${0}"),
+            '\\' => match chars.next() {
+                Some(d) if d.is_ascii_digit() => out.push_str(&format!("${{{}}}", d)),
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some('$') => out.push_str("$$"),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            },
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse("10"), Ok(ExCommand::Goto(Address::Line(10))));
+        assert_eq!(parse("$"), Ok(ExCommand::Goto(Address::Last)));
+        assert_eq!(parse("+3"), Ok(ExCommand::Goto(Address::Offset(Box::new(Address::Current), 3))));
+        assert_eq!(parse("'a-"), Ok(ExCommand::Goto(Address::Offset(Box::new(Address::Mark('a')), -1))));
+        assert_eq!(parse("2,5"), Ok(ExCommand::Goto(Address::Line(5))));
+        assert_eq!(parse(",5").unwrap_err().message, "Missing start of range");
+        assert_eq!(parse("1,").unwrap_err().message, "Missing end of range");
+    }
+
+    #[test]
+    fn parses_substitute() {
+        assert_eq!(parse("%s/a\\/b/c/gi"), Ok(ExCommand::Substitute {
+            range: LineRange::Whole,
+            pattern: String::from("a/b"),
+            replacement: String::from("c"),
+            global: true,
+            ignore_case: true,
+        }));
+        assert_eq!(parse(".,$s#x#y"), Ok(ExCommand::Substitute {
+            range: LineRange::Span(Address::Current, Address::Last),
+            pattern: String::from("x"),
+            replacement: String::from("y"),
+            global: false,
+            ignore_case: false,
+        }));
+        assert_eq!(parse("s//x/").unwrap_err().message, "Empty search pattern");
+        assert_eq!(parse("s/x/y/z").unwrap_err().message, "Unknown flag 'z'");
+    }
+
+    #[test]
+    fn parses_names_and_arguments() {
+        assert_eq!(parse("w! out.txt"), Ok(ExCommand::Write { path: Some(String::from("out.txt")), force: true }));
+        assert_eq!(parse("e!"), Ok(ExCommand::Edit { path: None, force: true }));
+        assert_eq!(parse("set tab_width=2"), Ok(ExCommand::Set { option: String::from("tab_width"), value: Some(String::from("2")) }));
+        assert_eq!(parse("sort! nu"), Ok(ExCommand::Sort { range: LineRange::Default, reverse: true, numeric: true, ignore_case: false, unique: true }));
+        assert_eq!(parse("3q").unwrap_err().message, "'q' does not take a range");
+        assert_eq!(parse("frobnicate").unwrap_err(), ParseError { message: String::from("Not an editor command: frobnicate"), position: 0 });
+    }
+
+    #[test]
+    fn parses_marks() {
+        assert_eq!(parse("ka"), Ok(ExCommand::Mark { range: LineRange::Default, name: 'a' }));
+        assert_eq!(parse("3k b"), Ok(ExCommand::Mark { range: LineRange::Single(Address::Line(3)), name: 'b' }));
+        assert_eq!(parse("mark c"), Ok(ExCommand::Mark { range: LineRange::Default, name: 'c' }));
+        assert_eq!(parse("kA").unwrap_err().message, "Invalid mark name 'A' (use a-z)");
+    }
+
+    fn strings(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn sorts_lines() {
+        let lines = strings(&["b", "C", "a", "B"]);
+        assert_eq!(sort_lines(&lines, false, false, false, false), strings(&["B", "C", "a", "b"]));
+        assert_eq!(sort_lines(&lines, false, false, true, false), strings(&["a", "b", "B", "C"]));
+        assert_eq!(sort_lines(&lines, true, false, true, false), strings(&["C", "B", "b", "a"]));
+        assert_eq!(sort_lines(&lines, false, false, true, true), strings(&["a", "b", "C"]));
+    }
+
+    #[test]
+    fn sorts_lines_by_number() {
+        let lines = strings(&["x10", "none", "x-3", "x2", "y2"]);
+        assert_eq!(sort_lines(&lines, false, true, false, false), strings(&["none", "x-3", "x2", "y2", "x10"]));
+        assert_eq!(sort_lines(&lines, false, true, false, true), strings(&["none", "x-3", "x2", "x10"]));
+    }
+
+    #[test]
+    fn translates_replacements() {
+        assert_eq!(translate_replacement("\\1-&"), "${1}-${0}");
+        assert_eq!(translate_replacement("$5\\n"), "$$5\n");
+        assert_eq!(translate_replacement("\\$1\\&"), "$$1&");
+    }
+}
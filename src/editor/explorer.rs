@@ -0,0 +1,280 @@
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+///
+/// ### A row in the file explorer
+///
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// Path relative to the working directory
+    pub path: PathBuf,
+    pub name: String,
+    pub depth: usize,
+    pub is_dir: bool,
+}
+
+///
+/// ### File operation waiting on user input
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExplorerAction {
+    Create,
+    Rename,
+    Move,
+    Delete,
+}
+
+///
+/// ### Tree view of the working directory
+///
+/// Directories are only read once they are expanded, and `entries` holds
+/// just the rows that are currently visible.
+///
+pub struct Explorer {
+    pub visible: bool,
+    pub entries: Vec<Entry>,
+    pub selected: usize,
+    pub scroll: usize,
+    expanded: HashSet<PathBuf>,
+    /// Action the current prompt is for, along with the prompt text
+    pub pending: Option<(ExplorerAction, String)>,
+}
+
+impl Explorer {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            entries: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            expanded: HashSet::new(),
+            pending: None,
+        }
+    }
+
+    pub fn selected_entry(&self) -> Option<&Entry> {
+        self.entries.get(self.selected)
+    }
+
+    /// Rebuild the visible rows from disk, keeping the selection where possible
+    pub fn refresh(&mut self) {
+        let selected = self.selected_entry().map(|e| e.path.clone());
+
+        self.entries.clear();
+        let mut entries = Vec::new();
+        self.read_dir(Path::new(""), 0, &mut entries);
+        self.entries = entries;
+
+        self.selected = selected
+            .and_then(|path| self.entries.iter().position(|e| e.path == path))
+            .unwrap_or(self.selected)
+            .min(self.entries.len().saturating_sub(1));
+    }
+
+    fn read_dir(&self, dir: &Path, depth: usize, out: &mut Vec<Entry>) {
+        let read_path = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        let mut children: Vec<Entry> = match fs::read_dir(read_path) {
+            Ok(entries) => entries.flatten()
+                .map(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    Entry {
+                        path: dir.join(&name),
+                        is_dir: entry.path().is_dir(),
+                        name,
+                        depth,
+                    }
+                })
+                .collect(),
+            Err(_) => return,
+        };
+
+        // directories first, then alphabetical
+        children.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase())));
+
+        for child in children {
+            let expand = child.is_dir && self.expanded.contains(&child.path);
+            let path = child.path.clone();
+            out.push(child);
+            if expand {
+                self.read_dir(&path, depth + 1, out);
+            }
+        }
+    }
+
+    pub fn is_expanded(&self, path: &Path) -> bool {
+        self.expanded.contains(path)
+    }
+
+    /// Expand or collapse the selected directory
+    pub fn toggle(&mut self) {
+        let entry = match self.selected_entry() {
+            Some(entry) if entry.is_dir => entry.clone(),
+            _ => return,
+        };
+
+        if !self.expanded.remove(&entry.path) {
+            self.expanded.insert(entry.path);
+        }
+        self.refresh();
+    }
+
+    /// Collapse the selected directory, or jump to the parent directory
+    pub fn collapse(&mut self) {
+        let entry = match self.selected_entry() {
+            Some(entry) => entry.clone(),
+            None => return,
+        };
+
+        if entry.is_dir && self.expanded.remove(&entry.path) {
+            self.refresh();
+            return;
+        }
+
+        if let Some(parent) = entry.path.parent() {
+            if let Some(index) = self.entries.iter().position(|e| e.path == parent) {
+                self.selected = index;
+            }
+        }
+    }
+
+    pub fn next(&mut self) {
+        if self.selected + 1 < self.entries.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn prev(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Expand every directory leading to `filename` and select it
+    pub fn reveal(&mut self, filename: &str) {
+        let path = normalize(Path::new(filename));
+        if path.as_os_str().is_empty() || path.is_absolute() {
+            self.refresh();
+            return;
+        }
+
+        let mut ancestor = PathBuf::new();
+        if let Some(parent) = path.parent() {
+            for component in parent.components() {
+                ancestor.push(component);
+                self.expanded.insert(ancestor.clone());
+            }
+        }
+
+        self.refresh();
+        if let Some(index) = self.entries.iter().position(|e| e.path == path) {
+            self.selected = index;
+        }
+    }
+
+    /// Directory new files should be created in, based on the selection
+    pub fn target_dir(&self) -> PathBuf {
+        match self.selected_entry() {
+            Some(entry) if entry.is_dir => entry.path.clone(),
+            Some(entry) => entry.path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            None => PathBuf::new(),
+        }
+    }
+
+    /// Create a file, or a directory if `name` ends with `/`
+    pub fn create(&mut self, name: &str) -> io::Result<PathBuf> {
+        let dir = self.target_dir();
+        let path = dir.join(name.trim_end_matches('/'));
+        if path.exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "already exists"));
+        }
+
+        if name.ends_with('/') {
+            fs::create_dir_all(&path)?;
+        } else {
+            if let Some(parent) = path.parent() {
+                if !parent.as_os_str().is_empty() {
+                    fs::create_dir_all(parent)?;
+                }
+            }
+            fs::OpenOptions::new().write(true).create_new(true).open(&path)?;
+        }
+
+        self.expanded.insert(dir);
+        self.refresh();
+        if let Some(index) = self.entries.iter().position(|e| e.path == path) {
+            self.selected = index;
+        }
+        Ok(path)
+    }
+
+    /// Move the selected entry to `to` (relative to the working directory)
+    ///
+    /// Refuses to overwrite anything. Returns the (old, new) paths.
+    pub fn rename(&mut self, to: &Path) -> io::Result<(PathBuf, PathBuf)> {
+        let from = match self.selected_entry() {
+            Some(entry) => entry.path.clone(),
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "nothing selected")),
+        };
+
+        let to = normalize(to);
+        if to.exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "target already exists"));
+        }
+        if let Some(parent) = to.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        fs::rename(&from, &to)?;
+
+        if self.expanded.remove(&from) {
+            self.expanded.insert(to.clone());
+        }
+        if let Some(parent) = to.parent() {
+            if !parent.as_os_str().is_empty() {
+                self.reveal(&to.to_string_lossy());
+                return Ok((from, to));
+            }
+        }
+        self.refresh();
+        if let Some(index) = self.entries.iter().position(|e| e.path == to) {
+            self.selected = index;
+        }
+        Ok((from, to))
+    }
+
+    /// Delete the selected entry, directories recursively
+    pub fn delete(&mut self) -> io::Result<PathBuf> {
+        let entry = match self.selected_entry() {
+            Some(entry) => entry.clone(),
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "nothing selected")),
+        };
+
+        if entry.is_dir {
+            fs::remove_dir_all(&entry.path)?;
+            self.expanded.retain(|p| !p.starts_with(&entry.path));
+        } else {
+            fs::remove_file(&entry.path)?;
+        }
+        self.refresh();
+        Ok(entry.path)
+    }
+}
+
+/// Strip `./` and resolve `..` lexically, so paths compare equal to explorer paths
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    out.push("..");
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
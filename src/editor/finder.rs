@@ -13,7 +13,7 @@ impl Finder {
         }
     }
 
-    pub fn find(&mut self, lines: Vec<String>, current_line: u16) {
+    pub fn find(&mut self, lines: Vec<String>, _current_line: u16) {
         self.search_results.clear();
 
         for (i, line) in lines.iter().enumerate() {
@@ -33,7 +33,7 @@ impl Finder {
     }
 
     pub fn next(&mut self) {
-        if self.search_results.len() == 0 {
+        if self.search_results.is_empty() {
             return;
         }
 
@@ -44,7 +44,7 @@ impl Finder {
     }
 
     pub fn prev(&mut self) {
-        if self.search_results.len() == 0 {
+        if self.search_results.is_empty() {
             return;
         }
 
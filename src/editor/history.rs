@@ -2,13 +2,18 @@ use super::{
     cursor::Cursor,
     commands::Command,
 };
-use crate::utils::utils::dbg;
 use std::collections::VecDeque;
 
 pub struct History {
     undo_stack: VecDeque<HistoryEntry>,
     redo_stack: VecDeque<HistoryEntry>,
     max_size: usize,
+    /// Identifies the buffer's current contents, every edit gets a new one
+    state: usize,
+    /// The last state handed out
+    last_state: usize,
+    /// The state the buffer was in when it was last saved
+    saved: usize,
 }
 
 #[derive(Clone, Debug)]
@@ -16,6 +21,8 @@ pub struct HistoryEntry {
     pub cursors: Vec<Cursor>,
     pub lines: Vec<String>,
     command: Command,
+    /// The state these lines are
+    state: usize,
 }
 
 impl History {
@@ -24,6 +31,30 @@ impl History {
             undo_stack: VecDeque::new(),
             redo_stack: VecDeque::new(),
             max_size: 100,
+            state: 0,
+            last_state: 0,
+            saved: 0,
+        }
+    }
+
+    /// Remember the current state as the one on disk
+    pub fn mark_saved(&mut self) {
+        self.saved = self.state;
+    }
+
+    /// Whether undoing or redoing got back to the state that was saved
+    pub fn is_saved(&self) -> bool {
+        self.state == self.saved
+    }
+
+    /// Change how many steps are kept, dropping the oldest ones if needed
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        while self.undo_stack.len() > max_size {
+            self.undo_stack.pop_front();
+        }
+        while self.redo_stack.len() > max_size {
+            self.redo_stack.pop_front();
         }
     }
 
@@ -40,8 +71,16 @@ impl History {
         self.redo_stack.len()
     }
 
+    /// Record the state before an edit, the edit makes a new one
+    pub fn push_history(&mut self, mut entry: HistoryEntry) {
+        entry.state = self.state;
+        self.last_state += 1;
+        self.state = self.last_state;
+        self.push_undo(entry);
+    }
+
     /// Ensure undo stack is within normal size when pushing entries to it
-    pub fn push_history(&mut self, entry: HistoryEntry) {
+    fn push_undo(&mut self, entry: HistoryEntry) {
         if self.len() > self.max_size {
             self.undo_stack.pop_front();
         }
@@ -56,8 +95,11 @@ impl History {
         self.redo_stack.push_back(entry);
     }
 
+    /// Take back the last entry, for an edit that didn't change anything
     pub fn pop_back(&mut self) -> Option<HistoryEntry> {
-        self.undo_stack.pop_back()
+        let entry = self.undo_stack.pop_back()?;
+        self.state = entry.state;
+        Some(entry)
     }
 
     /// Logical driver for the undo method
@@ -79,9 +121,10 @@ impl History {
                         last_entry = entry.clone();
                         self.push_redo(entry.clone());
                     } else {
-                        return Some(last_entry);
+                        break;
                     }
                 }
+                self.state = last_entry.state;
                 Some(last_entry)
             },
             None => None,
@@ -100,15 +143,16 @@ impl History {
                 let mut last_entry = entry.clone();
 
                 //redo entries until we get to a backspace
-                self.push_history(entry.clone());
+                self.push_undo(entry.clone());
                 while last_entry.command == Command::AddChar {
                     if let Some(entry) = self.redo_stack.pop_back() {
                         last_entry = entry.clone();
-                        self.push_history(entry.clone());
+                        self.push_undo(entry.clone());
                     } else {
-                        return Some(last_entry);
+                        break;
                     }
                 }
+                self.state = last_entry.state;
                 Some(last_entry)
             }
             None => None,
@@ -118,11 +162,13 @@ impl History {
 }
 
 impl HistoryEntry {
+    #[allow(dead_code)]
     pub fn new() -> HistoryEntry {
         Self {
             cursors: Vec::new(),
             lines: Vec::new(),
-            command: Command::NULL,
+            command: Command::Null,
+            state: 0,
         }
     }
 
@@ -131,6 +177,7 @@ impl HistoryEntry {
             cursors,
             lines,
             command,
+            state: 0,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,434 @@
+use crate::utils::config::Settings;
+use regex::Regex;
+use std::iter;
+
+use super::language;
+
+/// Lines looked at to guess how a file is indented
+const DETECT_LINES: usize = 10_000;
+
+/// How far up auto-indentation looks for an open bracket or a comment start
+const LOOK_BACK: usize = 200;
+
+///
+/// ### Indentation a buffer overrides
+///
+/// Each buffer has one guessed from its contents when it's opened and one
+/// set with `:set tab_width`, `:set hard_tabs` or `:retab`. Fields that are
+/// None leave the configured value alone.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Indent {
+    pub hard_tabs: Option<bool>,
+    pub width: Option<usize>,
+}
+
+impl Indent {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(hard_tabs) = self.hard_tabs {
+            settings.hard_tabs = hard_tabs;
+        }
+        if let Some(width) = self.width {
+            settings.tab_width = width;
+        }
+    }
+}
+
+/// Indentation as shown in the header, like `4 spaces` or `tabs`
+pub fn describe(settings: &Settings) -> String {
+    match settings.hard_tabs {
+        true => String::from("tabs"),
+        false => format!("{} spaces", settings.tab_width),
+    }
+}
+
+/// Width of the leading whitespace of `line` in columns, with tab stops every `tab_width`
+pub fn columns(line: &str, tab_width: usize) -> usize {
+    let mut columns = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => columns += 1,
+            '\t' => columns = (columns / tab_width + 1) * tab_width,
+            _ => break,
+        }
+    }
+    columns
+}
+
+/**
+ * Where backspace deletes from when the cursor is after `before`
+ *
+ * In the indentation it goes back to the previous level, deleting
+ * spaces up to the last tab stop, otherwise one character.
+ */
+pub fn backspace_start(before: &str, tab_width: usize) -> usize {
+    let previous = before.char_indices().last().map_or(0, |(i, _)| i);
+    if !before.ends_with(' ') || leading(before).len() != before.len() {
+        return previous;
+    }
+
+    let stop = (columns(before, tab_width) - 1) / tab_width * tab_width;
+    let mut start = before.len();
+    while before[..start].ends_with(' ') && columns(&before[..start], tab_width) > stop {
+        start -= 1;
+    }
+    start
+}
+
+/// The leading whitespace of `line`
+pub fn leading(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/**
+ * Guess how `lines` are indented
+ *
+ * Tabs win if more lines start with one than with spaces. The width of
+ * space indentation is the step seen most often between a line and the
+ * next one that is indented further. Lines inside block comments (` * `)
+ * are skipped, they are indented by one space.
+ */
+pub fn detect(lines: &[String]) -> Indent {
+    let (mut tabbed, mut spaced) = (0, 0);
+    let mut steps = [0usize; 9];
+    let mut previous = 0;
+
+    for line in lines.iter().take(DETECT_LINES) {
+        let rest = line.trim_start_matches([' ', '\t']);
+        if rest.is_empty() || rest.starts_with('*') {
+            continue;
+        }
+        let whitespace = leading(line);
+        if whitespace.starts_with('\t') {
+            tabbed += 1;
+            continue;
+        }
+
+        let width = whitespace.len();
+        if width > 0 {
+            spaced += 1;
+        }
+        if width > previous && width - previous < steps.len() {
+            steps[width - previous] += 1;
+        }
+        previous = width;
+    }
+
+    if tabbed > spaced {
+        return Indent { hard_tabs: Some(true), width: None };
+    }
+    // on a tie the wider step wins
+    let width = (2..steps.len()).max_by_key(|step| steps[*step]).filter(|step| steps[*step] > 0);
+    match width {
+        Some(width) => Indent { hard_tabs: Some(false), width: Some(width) },
+        None if spaced > 0 => Indent { hard_tabs: Some(false), width: None },
+        None => Indent::new(),
+    }
+}
+
+/**
+ * Rewrite the indentation of `line` in a new style
+ *
+ * The line's levels are counted with `from` as the indent width and
+ * written back with `to`. Spaces that don't make up a whole level are
+ * kept. Returns None for lines that don't change.
+ */
+pub fn retab(line: &str, from: usize, to: &Settings) -> Option<String> {
+    let whitespace = leading(line);
+    if whitespace.len() == line.len() {
+        return None;
+    }
+    let columns = columns(whitespace, from);
+    let mut indented = to.indent_unit().repeat(columns / from);
+    indented.push_str(&" ".repeat(columns % from));
+    indented.push_str(&line[whitespace.len()..]);
+    (indented != line).then_some(indented)
+}
+
+/// Whitespace `columns` wide, in the style of `settings`
+pub fn whitespace(columns: usize, settings: &Settings) -> String {
+    match settings.hard_tabs {
+        true => "\t".repeat(columns / settings.tab_width) + &" ".repeat(columns % settings.tab_width),
+        false => " ".repeat(columns),
+    }
+}
+
+/// Columns `text` takes up on screen, with tab stops every `tab_width`
+pub fn display_width(text: &str, tab_width: usize) -> usize {
+    text.chars().fold(0, |column, c| match c {
+        '\t' => (column / tab_width + 1) * tab_width,
+        _ => column + 1,
+    })
+}
+
+/// Brackets in `text` with their byte offsets, leaving out ones inside double quoted strings
+fn brackets(text: &str) -> Vec<(usize, char)> {
+    let mut found = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            '(' | '[' | '{' | ')' | ']' | '}' if !quoted => found.push((i, c)),
+            _ => {}
+        }
+    }
+    found
+}
+
+/// Brackets of `text` still open at its end, innermost last
+fn open_brackets(text: &str) -> Vec<(usize, char)> {
+    let mut open = Vec::new();
+    for (i, c) in brackets(text) {
+        match c {
+            '(' | '[' | '{' => open.push((i, c)),
+            _ => {
+                open.pop();
+            }
+        }
+    }
+    open
+}
+
+/// How many brackets `text` closes that were opened before it
+fn closed_before(text: &str) -> usize {
+    let mut depth = 0usize;
+    let mut closed = 0;
+    for (_, c) in brackets(text) {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            _ if depth > 0 => depth -= 1,
+            _ => closed += 1,
+        }
+    }
+    closed
+}
+
+/// The last of `previous` that opens the bracket `count` levels out, looking up from the end
+fn opening_line(previous: &[String], mut count: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (index, line) in previous.iter().enumerate().rev().take(LOOK_BACK) {
+        for (_, c) in brackets(line).into_iter().rev() {
+            match c {
+                ')' | ']' | '}' => depth += 1,
+                _ if depth > 0 => depth -= 1,
+                _ => {
+                    count -= 1;
+                    if count == 0 {
+                        return Some(index);
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Whether the end of `text` is inside a `/* */` comment, looking up through `previous`
+fn in_block_comment(previous: &[String], text: &str) -> bool {
+    for line in iter::once(text).chain(previous.iter().rev().take(LOOK_BACK).map(String::as_str)) {
+        match (line.rfind("/*"), line.rfind("*/")) {
+            (Some(open), Some(close)) => return open > close,
+            (Some(_), None) => return true,
+            (None, Some(_)) => return false,
+            (None, None) => {}
+        }
+    }
+    false
+}
+
+///
+/// ### Auto-indentation for the active buffer's language
+///
+/// Built from the language's `IndentRules`. Files of other types only keep
+/// the indentation of the line above, and indent after `indent_after`.
+///
+pub struct Indenter {
+    filetype: Option<&'static str>,
+    /// Whether the language has rules, which turns on bracket handling
+    known: bool,
+    increase: Option<Regex>,
+    decrease: Option<Regex>,
+    dedent_next: Option<Regex>,
+    block_comments: bool,
+}
+
+impl Indenter {
+    pub fn new(filetype: Option<&'static str>) -> Self {
+        let rules = filetype.and_then(language::indent_rules);
+        let regex = |pattern: &str| Some(pattern).filter(|pattern| !pattern.is_empty()).and_then(|pattern| Regex::new(pattern).ok());
+        Self {
+            filetype,
+            known: rules.is_some(),
+            increase: rules.and_then(|rules| regex(rules.increase)),
+            decrease: rules.and_then(|rules| regex(rules.decrease)),
+            dedent_next: rules.and_then(|rules| regex(rules.dedent_next)),
+            block_comments: rules.is_some_and(|rules| rules.block_comments),
+        }
+    }
+
+    pub fn filetype(&self) -> Option<&'static str> {
+        self.filetype
+    }
+
+    fn matches(regex: &Option<Regex>, text: &str) -> bool {
+        regex.as_ref().is_some_and(|regex| regex.is_match(text))
+    }
+
+    /**
+     * Indentation for a new line after `before`, the text left of the
+     * cursor when enter is pressed, with `previous` the lines above it
+     *
+     * The second value is the indentation for `after`, the text right of
+     * the cursor, when it should go on a line of its own, as when enter
+     * is pressed between `{` and `}`.
+     */
+    pub fn new_line(&self, previous: &[String], before: &str, after: &str, settings: &Settings) -> (String, Option<String>) {
+        let width = settings.tab_width;
+        let own = leading(before);
+        let trimmed = before.trim();
+
+        // keep writing a /* */ comment, lined up with its stars
+        if self.block_comments && in_block_comment(previous, before) {
+            let indent = match trimmed {
+                _ if trimmed.starts_with("/*") => format!("{} * ", own),
+                _ if trimmed.starts_with('*') => format!("{}* ", own),
+                _ => own.to_string(),
+            };
+            return (indent, None);
+        }
+        // the line after a comment's closing " */" goes back to where its "/*" was
+        if self.block_comments && trimmed.starts_with('*') && trimmed.ends_with("*/") {
+            let columns = columns(own, width);
+            return (whitespace(columns - columns % width, settings), None);
+        }
+
+        let mut base = match settings.auto_indent {
+            true => columns(own, width),
+            false => 0,
+        };
+        if self.known {
+            // a line closing brackets opened further up goes back to where they were opened
+            let closed = closed_before(before);
+            if closed > 0 {
+                if let Some(index) = opening_line(previous, closed) {
+                    base = columns(&previous[index], width);
+                }
+            }
+
+            if let Some(&(i, bracket)) = open_brackets(before).last() {
+                let inside = &before[i + 1..];
+                if bracket != '{' && !inside.trim().is_empty() {
+                    // line up with the first argument after an open ( or [
+                    let start = i + 1 + (inside.len() - inside.trim_start().len());
+                    let column = display_width(&before[..start], width);
+                    return (format!("{}{}", own, " ".repeat(column - columns(own, width))), None);
+                }
+                return self.opened(base, after, settings);
+            }
+        }
+
+        if Self::matches(&self.increase, trimmed) || trimmed.chars().last().is_some_and(|c| settings.indent_after.contains(c)) {
+            return self.opened(base, after, settings);
+        }
+        if Self::matches(&self.dedent_next, trimmed) {
+            base = base.saturating_sub(width) / width * width;
+        }
+        (whitespace(base, settings), None)
+    }
+
+    /// Indentation after a line that opens a block at `base` columns
+    fn opened(&self, base: usize, after: &str, settings: &Settings) -> (String, Option<String>) {
+        let inner = whitespace(base + settings.tab_width, settings);
+        let after = after.trim_start();
+        let closes = after.starts_with([')', ']', '}']) || Self::matches(&self.decrease, after);
+        (inner, closes.then(|| whitespace(base, settings)))
+    }
+
+    /**
+     * Indentation `line` should be moved back to after a character was
+     * typed in it, if it now starts with something like `}` or `else`
+     *
+     * `before` is the part of the line left of the cursor. Brackets go back
+     * to the line that opened them, words to the nearest line above that is
+     * indented less, and only if the line still has the indentation it got
+     * on enter (it isn't indented less than the line above).
+     */
+    pub fn dedent(&self, previous: &[String], line: &str, before: &str, settings: &Settings) -> Option<String> {
+        let width = settings.tab_width;
+        let trimmed = before.trim_start();
+        if trimmed.is_empty() || !Self::matches(&self.decrease, trimmed) {
+            return None;
+        }
+        let own = leading(line);
+        let current = columns(own, width);
+
+        let target = if trimmed.starts_with([')', ']', '}']) {
+            let index = opening_line(previous, 1)?;
+            leading(&previous[index]).to_string()
+        } else {
+            let above = previous.iter().rev().find(|line| !line.trim().is_empty())?;
+            if current < columns(above, width) {
+                return None;
+            }
+            let outer = previous.iter().rev().take(LOOK_BACK)
+                .find(|line| !line.trim().is_empty() && columns(line, width) < current)?;
+            leading(outer).to_string()
+        };
+        (target != own).then_some(target)
+    }
+
+    /// Whether `/` was just typed after the ` * ` a comment line was started with, to close it
+    pub fn closes_comment(&self, before: &str) -> bool {
+        self.block_comments && before.trim_start() == "* /"
+    }
+
+    /// Indentation a line typed after `previous` starts with
+    pub fn expected(&self, previous: &[String], settings: &Settings) -> String {
+        match previous.split_last() {
+            Some((above, rest)) => self.new_line(rest, above, "", settings).0,
+            None => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn detects_the_most_common_step() {
+        let spaces = lines("fn main() {\n    if x {\n        y();\n    }\n    z();\n}");
+        assert_eq!(detect(&spaces), Indent { hard_tabs: Some(false), width: Some(4) });
+
+        let two = lines("a:\n  b:\n    c: 1\n  d: 2");
+        assert_eq!(detect(&two), Indent { hard_tabs: Some(false), width: Some(2) });
+    }
+
+    #[test]
+    fn detects_tabs() {
+        let tabs = lines("int main() {\n\tif (x) {\n\t\ty();\n\t}\n}");
+        assert_eq!(detect(&tabs), Indent { hard_tabs: Some(true), width: None });
+    }
+
+    #[test]
+    fn skips_block_comments() {
+        let commented = lines("/**\n * Docs\n */\nfn f() {\n    g();\n}");
+        assert_eq!(detect(&commented), Indent { hard_tabs: Some(false), width: Some(4) });
+    }
+
+    #[test]
+    fn unindented_text_says_nothing() {
+        assert_eq!(detect(&lines("one\ntwo\n\nthree")), Indent::new());
+    }
+}
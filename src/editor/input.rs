@@ -3,14 +3,25 @@ use crossterm::event::{KeyCode, KeyModifiers};
 use super::{
     editor::Editor,
     commands::Command,
-    finder::Finder
+    completion::expand_tilde,
+    explorer::ExplorerAction,
+    finder::Finder,
+    window::{FocusDirection, SplitDirection},
+    ex,
+    large
 };
 
-use std::{env, fs};
+use std::{env, fs, path::PathBuf};
 
-use crate::utils::files::{open_file, save_file};
+use crate::utils::{
+    diff::diff_lines,
+    files::{open_file, save_file},
+    watcher
+};
 use crate::{get_line_len, get_line_len_int, get_lines_len};
 
+const OPEN_FILE_PROMPT: &str = "Open file: ";
+
 /**
  * Handle any CTRL + key events
  * 
@@ -19,7 +30,11 @@ use crate::{get_line_len, get_line_len_int, get_lines_len};
  * @param modifier: KeyModifiers - The modifier of the key event (should be CTRL, maybe more?)
  * We should probably pass in current state of the editor too, if we want to move the cursor
  */
-pub fn handle_ctrl(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers) -> () {
+pub fn handle_ctrl(editor: &mut Editor, code: KeyCode, _modifier: KeyModifiers) {
+    let edits = matches!(code, KeyCode::Char('z') | KeyCode::Char('y') | KeyCode::Char('v') | KeyCode::Char('h') | KeyCode::Char('/') | KeyCode::Char('7'));
+    if edits && !editor.editable() {
+        return;
+    }
 
     match code {
         KeyCode::Char('z') => {
@@ -35,17 +50,44 @@ pub fn handle_ctrl(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers) -
         }
         KeyCode::Char('v') => {
             editor.notif_text = String::from("Paste from clipboard");
+            editor.changes_saved = false;
             editor.insert_string("Hello, world!".to_string());
         }
         KeyCode::Char('s') => {
-            save_file(&editor);
-            editor.notif_text = String::from("File saved");
+            if editor.read_only {
+                editor.notif_text = String::from("Buffer is read-only (use :w! to write it anyway)");
+                return;
+            }
+            match save_file(editor) {
+                Ok(_) => {
+                    editor.changes_saved = true;
+                    editor.format.mixed_endings = false;
+                    editor.notif_text = String::from("File saved");
+                }
+                Err(err) => {
+                    editor.notif_text = format!("Error saving file: {}", err);
+                }
+            }
         }
-        KeyCode::Char(':') => {
-            editor.notif_text = String::from("Command mode");
+        // most terminals can't report CTRL + :, so CTRL + e opens the command line too
+        KeyCode::Char(':') | KeyCode::Char('e') => {
+            editor.notif_text = String::from(":");
+            editor.command_mode = true;
+            editor.command = Command::Ex;
         }
         KeyCode::Char('p') => {
-            editor.notif_text = String::from("Open File");
+            let dir = match env::current_dir() {
+                Ok(dir) => dir,
+                Err(_) => {
+                    editor.notif_text = String::from("Error getting current directory");
+                    return;
+                }
+            };
+
+            editor.picker.open(&dir);
+            editor.notif_text = String::from("Open file (fuzzy)");
+            editor.command_mode = true;
+            editor.command = Command::FilePicker;
         }
         KeyCode::Char('g') => {
             editor.notif_text = String::from("Goto line: ");
@@ -57,6 +99,13 @@ pub fn handle_ctrl(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers) -
             editor.command_mode = true;
             editor.command = Command::MoveCursor;
         }
+        KeyCode::Char('k') => {
+            editor.jump_to_match();
+        }
+        // terminals send CTRL + / as CTRL + 7
+        KeyCode::Char('/') | KeyCode::Char('7') => {
+            editor.toggle_line_comment();
+        }
         KeyCode::Char('l') => {
             editor.notif_text = String::from("Select line");
         }
@@ -72,13 +121,39 @@ pub fn handle_ctrl(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers) -
         }
         KeyCode::Char('h') => { // CTRL + Backspace maps to CTRL + h
             editor.notif_text = String::from("Delete line");
-            editor.backspace_line();
+            editor.delete_lines();
         }
         KeyCode::Char('o') => {
-            editor.notif_text = String::from("Open file: ");
+            editor.completer.reset();
+            editor.notif_text = String::from(OPEN_FILE_PROMPT);
             editor.command_mode = true;
             editor.command = Command::OpenFile;
         }
+        KeyCode::Char('b') => {
+            editor.buffer_selection = editor.active_buffer;
+            editor.notif_text = String::from("Buffers");
+            editor.command_mode = true;
+            editor.command = Command::BufferList;
+        }
+        KeyCode::Char('n') => {
+            editor.new_buffer();
+            editor.notif_text = String::from("New buffer");
+        }
+        KeyCode::Char('w') => {
+            request_close(editor);
+        }
+        KeyCode::Char('q') => {
+            // force quit, unsaved changes are lost
+            editor.quit = true;
+        }
+        KeyCode::PageDown => {
+            editor.next_buffer();
+            editor.notif_text = format!("Buffer {} of {}", editor.active_buffer + 1, editor.buffer_count());
+        }
+        KeyCode::PageUp => {
+            editor.prev_buffer();
+            editor.notif_text = format!("Buffer {} of {}", editor.active_buffer + 1, editor.buffer_count());
+        }
         KeyCode::Right => {
             editor.right_line();
         }
@@ -98,7 +173,7 @@ pub fn handle_ctrl(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers) -
  * @param code: KeyCode - The key code of the key event
  * @param modifier: KeyModifiers - The modifier of the key event (should be CTRL, maybe more?)
  */
-pub fn handle_ctrl_shift(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers) -> () {
+pub fn handle_ctrl_shift(editor: &mut Editor, code: KeyCode, _modifier: KeyModifiers) {
     match code {
         KeyCode::Right => {
             for cursor in &mut editor.cursors {
@@ -122,23 +197,128 @@ pub fn handle_ctrl_shift(editor: &mut Editor, code: KeyCode, modifier: KeyModifi
                 if cursor.line == 0 {
                     continue;
                 }
-                cursor.expand_selection(cursor.line - 1, cursor.col);
+                let col = cursor.col.min(get_line_len_int!(editor, cursor.line - 1));
+                cursor.expand_selection(cursor.line - 1, col);
             }
         }
+        KeyCode::PageDown => {
+            editor.move_buffer(1);
+        }
+        KeyCode::PageUp => {
+            editor.move_buffer(-1);
+        }
         KeyCode::Down => {
             for cursor in &mut editor.cursors {
                 let mut new_line = cursor.line + 1;
-                if new_line > get_lines_len!(editor) {
-                    new_line = get_lines_len!(editor);
+                if new_line >= get_lines_len!(editor) {
+                    new_line = get_lines_len!(editor) - 1;
                 }
-                cursor.expand_selection(new_line, cursor.col);
+                let col = cursor.col.min(get_line_len_int!(editor, new_line));
+                cursor.expand_selection(new_line, col);
             }
         }
         _ => {}
     }
 }
 
-pub fn handle_command(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers) -> () {
+/**
+ * Handle any ALT + key events
+ *
+ * ALT + 1-9 jumps to that tab, ALT + 0 to the last one.
+ * ALT + i and ALT + a select inside and around brackets, ALT + / toggles a
+ * block comment. ALT + SHIFT + Up/Down move lines, ALT + d duplicates them
+ * and ALT + j joins them. The rest manage split windows.
+ */
+pub fn handle_alt(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers) {
+    let edits = match code {
+        KeyCode::Up | KeyCode::Down => modifier.contains(KeyModifiers::SHIFT),
+        KeyCode::Char(c) => "/dj".contains(c),
+        _ => false,
+    };
+    if edits && !editor.editable() {
+        return;
+    }
+
+    match code {
+        KeyCode::Char(c) if c.is_ascii_digit() => {
+            let index = match c.to_digit(10).unwrap() {
+                0 => editor.buffer_count() - 1,
+                n => n as usize - 1,
+            };
+            if index < editor.buffer_count() {
+                editor.switch_buffer(index);
+            }
+        }
+        KeyCode::Char('e') => {
+            toggle_explorer(editor);
+        }
+        KeyCode::Char('s') => {
+            editor.split_window(SplitDirection::Horizontal);
+            editor.notif_text = String::from("Split window");
+        }
+        KeyCode::Char('v') => {
+            editor.split_window(SplitDirection::Vertical);
+            editor.notif_text = String::from("Split window");
+        }
+        KeyCode::Char('w') => {
+            editor.focus_next_window();
+        }
+        KeyCode::Char('q') => {
+            if !editor.close_window() {
+                editor.notif_text = String::from("Cannot close last window");
+            }
+        }
+        KeyCode::Char('o') => {
+            editor.windows.only();
+            editor.notif_text = String::from("Closed other windows");
+        }
+        KeyCode::Char('=') => {
+            editor.windows.equalize();
+        }
+        KeyCode::Char('+') => {
+            editor.windows.resize(5);
+        }
+        KeyCode::Char('-') => {
+            editor.windows.resize(-5);
+        }
+        KeyCode::Char('/') => {
+            editor.toggle_block_comment();
+        }
+        KeyCode::Char('d') => {
+            editor.duplicate_lines();
+        }
+        KeyCode::Char('j') => {
+            editor.join_lines();
+        }
+        KeyCode::Up if modifier.contains(KeyModifiers::SHIFT) => editor.move_lines(true),
+        KeyCode::Down if modifier.contains(KeyModifiers::SHIFT) => editor.move_lines(false),
+        KeyCode::Char('i') => editor.select_brackets(false),
+        KeyCode::Char('a') => editor.select_brackets(true),
+        KeyCode::Left => editor.focus_window(FocusDirection::Left),
+        KeyCode::Right => editor.focus_window(FocusDirection::Right),
+        KeyCode::Up => editor.focus_window(FocusDirection::Up),
+        KeyCode::Down => editor.focus_window(FocusDirection::Down),
+        _ => {
+            editor.notif_text = String::from("Invalid command");
+        }
+    }
+}
+
+/**
+ * Whether a key leaves the selection alone, so it isn't cleared after it
+ */
+pub fn keeps_selection(code: KeyCode, modifiers: KeyModifiers) -> bool {
+    let (ctrl, alt) = (modifiers.contains(KeyModifiers::CONTROL), modifiers.contains(KeyModifiers::ALT));
+    let moves_lines = alt && modifiers.contains(KeyModifiers::SHIFT) && matches!(code, KeyCode::Up | KeyCode::Down);
+    modifiers.contains(KeyModifiers::CONTROL | KeyModifiers::SHIFT)
+        || moves_lines
+        || (!ctrl && !alt && matches!(code, KeyCode::Tab | KeyCode::BackTab))
+        || (alt && matches!(code, KeyCode::Char('i') | KeyCode::Char('a') | KeyCode::Char('/') | KeyCode::Char('d')))
+        // the command line can work on the selected lines
+        || (ctrl && matches!(code, KeyCode::Char('/') | KeyCode::Char('7') | KeyCode::Char(':') | KeyCode::Char('e')))
+}
+
+pub fn handle_command(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers) {
     
     //match for commands that don't need character input
     match editor.command {
@@ -175,6 +355,123 @@ pub fn handle_command(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers
     
             return;
         }
+        Command::FilePicker => {
+            match code {
+                KeyCode::Char(c) => {
+                    editor.picker.query.push(c);
+                    editor.picker.selected = 0;
+                    editor.picker.update_matches(&editor.recent_files);
+                }
+                KeyCode::Backspace => {
+                    editor.picker.query.pop();
+                    editor.picker.selected = 0;
+                    editor.picker.update_matches(&editor.recent_files);
+                }
+                KeyCode::Down | KeyCode::Tab => {
+                    editor.picker.next();
+                }
+                KeyCode::Up | KeyCode::BackTab => {
+                    editor.picker.prev();
+                }
+                KeyCode::Enter => {
+                    editor.command_mode = false;
+                    match editor.picker.selected_file().cloned() {
+                        Some(file) => request_open(editor, file),
+                        None => editor.notif_text = String::from("No matching file"),
+                    }
+                }
+                KeyCode::Esc => {
+                    editor.command_mode = false;
+                    editor.notif_text = String::from("Editor mode");
+                }
+                _ => {}
+            }
+            return;
+        }
+        Command::BufferList => {
+            match code {
+                KeyCode::Down | KeyCode::Char('j') => {
+                    editor.buffer_selection = (editor.buffer_selection + 1).min(editor.buffer_count() - 1);
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    editor.buffer_selection = editor.buffer_selection.saturating_sub(1);
+                }
+                KeyCode::Enter => {
+                    editor.command_mode = false;
+                    editor.switch_buffer(editor.buffer_selection);
+                    editor.notif_text = String::from("Editor mode");
+                }
+                KeyCode::Char('d') | KeyCode::Delete => {
+                    editor.command_mode = false;
+                    editor.switch_buffer(editor.buffer_selection);
+                    request_close(editor);
+                }
+                KeyCode::Esc => {
+                    editor.command_mode = false;
+                    editor.notif_text = String::from("Editor mode");
+                }
+                _ => {}
+            }
+            return;
+        }
+        Command::Explorer => {
+            handle_explorer(editor, code, modifier);
+            return;
+        }
+        Command::RecoverPrompt => {
+            handle_recover(editor, code);
+            return;
+        }
+        Command::QuitPrompt => {
+            handle_quit_prompt(editor, code);
+            return;
+        }
+        Command::ReloadPrompt => {
+            handle_reload(editor, code);
+            return;
+        }
+        Command::DiffView => {
+            match (code, editor.diff_view.as_mut()) {
+                (KeyCode::Up, Some(view)) => view.scroll = view.scroll.saturating_sub(1),
+                (KeyCode::Down, Some(view)) => view.scroll += 1,
+                (KeyCode::Char('d') | KeyCode::Esc, _) | (_, None) => {
+                    editor.diff_view = None;
+                    editor.command_mode = false;
+                    editor.notif_text = String::from("Editor mode");
+                }
+                _ => {}
+            }
+            return;
+        }
+        Command::ExplorerPrompt => {
+            let is_delete = matches!(editor.explorer.pending, Some((ExplorerAction::Delete, _)));
+            match code {
+                KeyCode::Esc => {
+                    editor.explorer.pending = None;
+                    editor.command = Command::Explorer;
+                    editor.notif_text = String::from("Explorer");
+                    return;
+                }
+                KeyCode::Char('y') if is_delete => {
+                    finish_explorer_action(editor, String::new());
+                    return;
+                }
+                KeyCode::Char('n') if is_delete => {
+                    editor.explorer.pending = None;
+                    editor.command = Command::Explorer;
+                    editor.notif_text = String::from("Delete cancelled");
+                    return;
+                }
+                _ if is_delete => return,
+                KeyCode::Enter => {
+                    let prompt_len = editor.explorer.pending.as_ref().map(|(_, p)| p.len()).unwrap_or(0);
+                    let input = editor.notif_text.get(prompt_len..).unwrap_or("").trim().to_string();
+                    finish_explorer_action(editor, input);
+                    return;
+                }
+                _ => {}
+            }
+        }
         Command::FindSelection => {
             match code {
                 KeyCode::Right | KeyCode::Down => {
@@ -205,27 +502,15 @@ pub fn handle_command(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers
             }
             return;
         }
+        Command::OpenFile if code == KeyCode::Tab || code == KeyCode::BackTab => {
+            let input = editor.notif_text.get(OPEN_FILE_PROMPT.len()..).unwrap_or("").to_string();
+            let completed = editor.completer.complete(&input, code == KeyCode::Tab);
+            editor.notif_text = format!("{}{}", OPEN_FILE_PROMPT, completed);
+            return;
+        }
         Command::OpenFile => {
-            if code == KeyCode::Tab {
-                let dir =  match env::current_dir() {
-                    Ok(dir) => dir,
-                    Err(_) => {
-                        editor.notif_text = String::from("Error getting current directory");
-                        editor.command_mode = false;
-                        return;
-                    }
-                };
-    
-                // editor.notif_text = format!("Current directory: {}", dir.display());
-                fs::read_dir(dir).unwrap().for_each(|entry| {
-                    match entry {
-                        Ok(entry) => {
-                            editor.dbg(entry.file_name().to_str().unwrap().to_string());
-                        }
-                        Err(_) => {}
-                    }
-                });
-            }
+            // any other key edits the input, so old candidates no longer apply
+            editor.completer.reset();
         }
         _ => {}
     }
@@ -235,14 +520,25 @@ pub fn handle_command(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers
             if editor.command == Command::SavePrompt {
                 match c {
                     'y' => {
-                        save_file(editor);
-                        editor.notif_text = String::from("File saved");
-                        editor.filename = editor.file_to_open.clone();
-                        open_file(editor);
+                        editor.command_mode = false;
+                        match save_file(editor) {
+                            Ok(_) => {
+                                editor.close_buffer();
+                                editor.notif_text = String::from("File saved, buffer closed");
+                            }
+                            Err(err) => {
+                                editor.notif_text = format!("Error saving file: {}", err);
+                            }
+                        }
                     }
                     'n' => {
-                        editor.filename = editor.file_to_open.clone();
-                        open_file(editor);
+                        editor.command_mode = false;
+                        editor.close_buffer();
+                        editor.notif_text = String::from("Buffer closed");
+                    }
+                    'c' => {
+                        editor.command_mode = false;
+                        editor.notif_text = String::from("Editor mode");
                     }
                     _ => {}
                 }
@@ -275,6 +571,14 @@ pub fn handle_command(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers
                         return;
                     }
 
+                    // large files go past what fits in the window (and in a u16)
+                    if editor.large.is_some() {
+                        let line: usize = string_parts[1].trim().parse().unwrap_or(1);
+                        let col: usize = string_parts.get(2).and_then(|col| col.trim().parse().ok()).unwrap_or(0);
+                        large::goto(editor, line.saturating_sub(1), col);
+                        return;
+                    }
+
                     //parse line number
                     let mut line: u16 = string_parts[1].trim().parse().unwrap();
                     if line > get_lines_len!(editor) {
@@ -299,11 +603,21 @@ pub fn handle_command(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers
                     }
 
                     let query: String = editor.notif_text.split_off("Find substring: ".len());
-                    if query.len() == 0 {
+                    if query.is_empty() {
                         editor.notif_text = String::from("Invalid substring!");
                         return;
                     }
 
+                    // the whole file is searched, one match at a time
+                    if editor.large.is_some() {
+                        editor.command_mode = false;
+                        editor.notif_text = match large::find(editor, &query) {
+                            Some(line) => format!("Found at line {} (search again for the next)", line + 1),
+                            None => String::from("No results found"),
+                        };
+                        return;
+                    }
+
                     editor.finder.query = query;
                     editor.finder.find(editor.lines.clone(), editor.cursors[0].line);
 
@@ -336,27 +650,449 @@ pub fn handle_command(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers
                         editor.cursors[0].line = 0;
                     }
 
-                    let query: String = editor.notif_text.split_off("Open fille:".len());
-                    if query.len() == 0 {
+                    let query = expand_tilde(editor.notif_text.get(OPEN_FILE_PROMPT.len()..).unwrap_or(""));
+                    if query.is_empty() {
                         editor.notif_text = String::from("Invalid filename!");
                         return;
                     }
 
-                    if !editor.changes_saved {
-                        editor.notif_text = String::from("Save changes before opening a new file? (y/n)");
-                        editor.command_mode = true;
-                        editor.command = Command::SavePrompt;
-                        editor.file_to_open = query;
-                        return;
-                    }
+                    request_open(editor, query);
+                }
+                Command::Ex => {
+                    editor.command_mode = false;
 
-                    save_file(editor);
-                    editor.filename = query;
-                    open_file(editor);
+                    let input = editor.notif_text.trim_start_matches(':').to_string();
+                    let result = match ex::parse(&input) {
+                        Ok(command) => ex::execute(editor, command),
+                        Err(err) => Err(err.to_string()),
+                    };
+
+                    editor.notif_text = match result {
+                        Ok(message) => message,
+                        Err(message) => format!("Error: {}", message),
+                    };
                 }
                 _ => {}
             }
         }
         _ => {}
     }
-}
\ No newline at end of file
+}
+
+/**
+ * Open a file in a new buffer, or switch to it if it is already open
+ */
+pub fn request_open(editor: &mut Editor, filename: String) {
+    if let Some(index) = editor.find_buffer(&filename) {
+        editor.switch_buffer(index);
+        editor.notif_text = format!("Switched to {}", filename);
+        return;
+    }
+
+    // reuse the empty buffer we start with instead of leaving it around
+    let reused = editor.is_scratch();
+    if !reused {
+        editor.new_buffer();
+    }
+    editor.filename = filename;
+    if open_file(editor) {
+        return;
+    }
+
+    // don't leave a buffer named after a file that couldn't be read
+    let notif_text = std::mem::take(&mut editor.notif_text);
+    if reused {
+        editor.filename = String::new();
+        editor.apply_settings();
+    } else {
+        editor.close_buffer();
+    }
+    editor.notif_text = notif_text;
+}
+
+/**
+ * Close the active buffer, asking to save first if there are unsaved changes
+ */
+pub fn request_close(editor: &mut Editor) {
+    if editor.changes_saved {
+        editor.close_buffer();
+        editor.notif_text = String::from("Buffer closed");
+        return;
+    }
+
+    let name = if editor.filename.is_empty() { "[No Name]" } else { editor.filename.as_str() };
+    editor.notif_text = format!("Save changes to {} before closing? (y/n/c)", name);
+    editor.command_mode = true;
+    editor.command = Command::SavePrompt;
+}
+
+/**
+ * Show the explorer and focus it, or hide it if it is already focused
+ */
+fn toggle_explorer(editor: &mut Editor) {
+    if editor.explorer.visible && editor.command_mode && editor.command == Command::Explorer {
+        editor.explorer.visible = false;
+        editor.command_mode = false;
+        editor.notif_text = String::from("Editor mode");
+        return;
+    }
+
+    if !editor.explorer.visible {
+        editor.explorer.visible = true;
+        let filename = editor.filename.clone();
+        editor.explorer.reveal(&filename);
+    }
+    editor.command_mode = true;
+    editor.command = Command::Explorer;
+    editor.notif_text = String::from("Explorer (a: new  r: rename  m: move  d: delete)");
+}
+
+/**
+ * Handle keys while the explorer sidebar has focus
+ */
+fn handle_explorer(editor: &mut Editor, code: KeyCode, modifier: KeyModifiers) {
+    if modifier.contains(KeyModifiers::ALT) {
+        if code == KeyCode::Char('e') {
+            toggle_explorer(editor);
+        }
+        return;
+    }
+
+    let selected = editor.explorer.selected_entry().cloned();
+    let prompt = |editor: &mut Editor, action: ExplorerAction, prompt: String, initial: &str| {
+        editor.notif_text = format!("{}{}", prompt, initial);
+        editor.explorer.pending = Some((action, prompt));
+        editor.command = Command::ExplorerPrompt;
+    };
+
+    match code {
+        KeyCode::Down | KeyCode::Char('j') => editor.explorer.next(),
+        KeyCode::Up | KeyCode::Char('k') => editor.explorer.prev(),
+        KeyCode::Left | KeyCode::Char('h') => editor.explorer.collapse(),
+        KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+            match selected {
+                Some(entry) if entry.is_dir => editor.explorer.toggle(),
+                Some(entry) => {
+                    editor.command_mode = false;
+                    request_open(editor, entry.path.to_string_lossy().to_string());
+                }
+                None => {}
+            }
+        }
+        KeyCode::Char('R') => {
+            editor.explorer.refresh();
+            editor.notif_text = String::from("Explorer refreshed");
+        }
+        KeyCode::Char('a') => {
+            let dir = editor.explorer.target_dir();
+            let location = if dir.as_os_str().is_empty() { String::from("./") } else { format!("{}/", dir.display()) };
+            prompt(editor, ExplorerAction::Create, format!("New in {} (end with / for folder): ", location), "");
+        }
+        KeyCode::Char('r') => {
+            if let Some(entry) = selected {
+                prompt(editor, ExplorerAction::Rename, String::from("Rename to: "), &entry.name);
+            }
+        }
+        KeyCode::Char('m') => {
+            if let Some(entry) = selected {
+                prompt(editor, ExplorerAction::Move, String::from("Move to: "), &entry.path.to_string_lossy());
+            }
+        }
+        KeyCode::Char('d') | KeyCode::Delete => {
+            if let Some(entry) = selected {
+                let kind = if entry.is_dir { "folder and everything in it" } else { "file" };
+                prompt(editor, ExplorerAction::Delete, format!("Delete {} {}? (y/n)", kind, entry.path.display()), "");
+            }
+        }
+        KeyCode::Esc => {
+            editor.command_mode = false;
+            editor.notif_text = String::from("Editor mode");
+        }
+        _ => {}
+    }
+}
+
+/**
+ * Run the file operation the explorer prompt was asking about
+ */
+fn finish_explorer_action(editor: &mut Editor, input: String) {
+    editor.command = Command::Explorer;
+    let action = match editor.explorer.pending.take() {
+        Some((action, _)) => action,
+        None => return,
+    };
+
+    if input.is_empty() && action != ExplorerAction::Delete {
+        editor.notif_text = String::from("Cancelled, no name given");
+        return;
+    }
+
+    let result = match action {
+        ExplorerAction::Create => editor.explorer.create(&input)
+            .map(|path| format!("Created {}", path.display())),
+        ExplorerAction::Rename | ExplorerAction::Move => {
+            let to = match (&action, editor.explorer.selected_entry()) {
+                (ExplorerAction::Rename, Some(entry)) => entry.path.with_file_name(&input),
+                _ => PathBuf::from(&input),
+            };
+            editor.explorer.rename(&to).map(|(from, to)| {
+                editor.rename_buffers(&from, &to);
+                format!("Moved {} to {}", from.display(), to.display())
+            })
+        }
+        ExplorerAction::Delete => editor.explorer.delete()
+            .map(|path| match editor.buffers_deleted(&path) {
+                0 => format!("Deleted {}", path.display()),
+                kept => format!("Deleted {}, {} unsaved buffer(s) kept without a name", path.display(), kept),
+            }),
+    };
+
+    editor.notif_text = match result {
+        Ok(message) => message,
+        Err(err) => format!("Error: {}", err),
+    };
+}
+
+/**
+ * Quit, asking what to do with unsaved buffers first if there are any
+ */
+pub fn request_quit(editor: &mut Editor) {
+    if editor.dirty_buffers().is_empty() {
+        editor.quit = true;
+        return;
+    }
+
+    editor.quit_selection = 0;
+    editor.quit_discarded.clear();
+    editor.notif_text = String::from("Unsaved changes, quit anyway?");
+    editor.command_mode = true;
+    editor.command = Command::QuitPrompt;
+}
+
+/**
+ * Buffers the quit prompt still has to ask about
+ */
+pub fn quit_pending(editor: &Editor) -> Vec<usize> {
+    editor.dirty_buffers()
+        .into_iter()
+        .filter(|i| !editor.quit_discarded.contains(i))
+        .collect()
+}
+
+fn handle_quit_prompt(editor: &mut Editor, code: KeyCode) {
+    let pending = quit_pending(editor);
+    let selected = pending.get(editor.quit_selection).copied();
+
+    match code {
+        KeyCode::Up => {
+            editor.quit_selection = editor.quit_selection.saturating_sub(1);
+        }
+        KeyCode::Down => {
+            if editor.quit_selection + 1 < pending.len() {
+                editor.quit_selection += 1;
+            }
+        }
+        KeyCode::Char('s') | KeyCode::Char('y') => {
+            if let Some(index) = selected {
+                save_buffer(editor, index);
+            }
+        }
+        KeyCode::Char('d') | KeyCode::Char('n') => {
+            if let Some(index) = selected {
+                editor.quit_discarded.push(index);
+            }
+        }
+        KeyCode::Char('S') | KeyCode::Char('a') => {
+            for index in pending {
+                if !save_buffer(editor, index) {
+                    break;
+                }
+            }
+        }
+        KeyCode::Char('D') | KeyCode::Char('!') => {
+            editor.quit = true;
+            return;
+        }
+        KeyCode::Esc | KeyCode::Char('c') => {
+            editor.command_mode = false;
+            editor.notif_text = String::from("Quit cancelled");
+            return;
+        }
+        _ => return,
+    }
+
+    let remaining = quit_pending(editor).len();
+    if remaining == 0 {
+        editor.quit = true;
+        return;
+    }
+    editor.quit_selection = editor.quit_selection.min(remaining - 1);
+}
+
+/// Switch to a buffer and save it, reporting errors in the notification area
+fn save_buffer(editor: &mut Editor, index: usize) -> bool {
+    editor.switch_buffer(index);
+    match save_file(editor) {
+        Ok(_) => {
+            editor.changes_saved = true;
+            editor.format.mixed_endings = false;
+            editor.notif_text = format!("Saved {}", editor.filename);
+            true
+        }
+        Err(err) => {
+            let name = if editor.filename.is_empty() { "[No Name]" } else { &editor.filename };
+            editor.notif_text = format!("Error saving {}: {}", name, err);
+            false
+        }
+    }
+}
+
+/**
+ * Ask what to do with the next swap file found when opening a file
+ *
+ * Called from the main loop whenever no other prompt is active, so files
+ * opened from anywhere (arguments, :e, the picker, ...) get asked about.
+ */
+pub fn prompt_recovery(editor: &mut Editor) {
+    let Some(recovery) = editor.recoveries.first() else {
+        return;
+    };
+
+    let running = match recovery.running_pid {
+        Some(pid) => format!(" (pte {} may still be editing it)", pid),
+        None => String::new(),
+    };
+    editor.notif_text = format!(
+        "Swap file found for {}{}: [r]ecover [d]iff [x] discard [esc] keep",
+        recovery.filename, running,
+    );
+
+    if let Some(index) = editor.find_buffer(&recovery.filename) {
+        editor.switch_buffer(index);
+    }
+    editor.command_mode = true;
+    editor.command = Command::RecoverPrompt;
+}
+
+fn handle_recover(editor: &mut Editor, code: KeyCode) {
+    if editor.recoveries.is_empty() {
+        editor.command_mode = false;
+        return;
+    }
+
+    match code {
+        KeyCode::Char('r') => {
+            let recovery = editor.recoveries.remove(0);
+            let lines = match (&recovery.patches, editor.large.as_mut()) {
+                (Some(patches), Some(large)) => large.apply_patches(patches),
+                _ => Some(recovery.lines),
+            };
+            let Some(lines) = lines else {
+                editor.notif_text = format!("Error: {} doesn't fit the file anymore", recovery.swap_path.display());
+                return;
+            };
+            editor.push_history(Command::AddChar);
+            editor.lines = lines;
+            editor.changes_saved = false;
+            editor.swap.adopt(recovery.swap_path);
+            editor.notif_text = format!("Recovered {}, save to keep the changes", recovery.filename);
+        }
+        KeyCode::Char('d') => {
+            let diff = match (&editor.recoveries[0].patches, &editor.large) {
+                (Some(patches), Some(large)) => patches.iter()
+                    .flat_map(|patch| diff_lines(&large.original(patch.start, patch.end), &patch.lines))
+                    .collect(),
+                _ => diff_lines(&editor.lines, &editor.recoveries[0].lines),
+            };
+            let recovery = &mut editor.recoveries[0];
+            recovery.diff = match recovery.diff {
+                Some(_) => None,
+                None => Some(diff),
+            };
+            recovery.diff_scroll = 0;
+            return;
+        }
+        KeyCode::Down => {
+            editor.recoveries[0].diff_scroll += 1;
+            return;
+        }
+        KeyCode::Up => {
+            let recovery = &mut editor.recoveries[0];
+            recovery.diff_scroll = recovery.diff_scroll.saturating_sub(1);
+            return;
+        }
+        KeyCode::Char('x') => {
+            let recovery = editor.recoveries.remove(0);
+            editor.notif_text = match fs::remove_file(&recovery.swap_path) {
+                Ok(_) => format!("Deleted swap file {}", recovery.swap_path.display()),
+                Err(err) => format!("Error: can't delete {}: {}", recovery.swap_path.display(), err),
+            };
+        }
+        KeyCode::Esc => {
+            let recovery = editor.recoveries.remove(0);
+            editor.notif_text = format!("Kept swap file {}", recovery.swap_path.display());
+        }
+        _ => return,
+    }
+
+    editor.command_mode = false;
+}
+
+/**
+ * Ask what to do with the next dirty buffer that changed on disk
+ */
+pub fn prompt_reload(editor: &mut Editor) {
+    let Some(change) = editor.external_changes.first() else {
+        return;
+    };
+
+    editor.notif_text = format!("{} changed on disk: [r]eload [d]iff [k]eep mine", change.filename);
+    if let Some(index) = editor.find_buffer(&change.filename) {
+        editor.switch_buffer(index);
+    }
+    editor.command_mode = true;
+    editor.command = Command::ReloadPrompt;
+}
+
+fn handle_reload(editor: &mut Editor, code: KeyCode) {
+    if editor.external_changes.is_empty() {
+        editor.command_mode = false;
+        return;
+    }
+
+    match code {
+        KeyCode::Char('r') => {
+            let change = editor.external_changes.remove(0);
+            if let Some(index) = editor.find_buffer(&change.filename) {
+                watcher::reload(editor, index);
+            }
+            editor.notif_text = format!("Reloaded {}", change.filename);
+        }
+        KeyCode::Char('d') => {
+            let lines = editor.lines.clone();
+            let change = &mut editor.external_changes[0];
+            change.diff = match change.diff {
+                Some(_) => None,
+                None => Some(diff_lines(&lines, &change.lines)),
+            };
+            change.diff_scroll = 0;
+            return;
+        }
+        KeyCode::Down => {
+            editor.external_changes[0].diff_scroll += 1;
+            return;
+        }
+        KeyCode::Up => {
+            let change = &mut editor.external_changes[0];
+            change.diff_scroll = change.diff_scroll.saturating_sub(1);
+            return;
+        }
+        KeyCode::Char('k') | KeyCode::Esc => {
+            let change = editor.external_changes.remove(0);
+            editor.notif_text = format!("Kept your changes to {}, saving will overwrite the file", change.filename);
+        }
+        _ => return,
+    }
+
+    editor.command_mode = false;
+}
@@ -0,0 +1,287 @@
+use crate::utils::config;
+use crossterm::event::{KeyCode, KeyModifiers};
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+
+type Key = (KeyCode, KeyModifiers);
+/// A problem in a keymap file and the line it is on
+type JsonError = (usize, String);
+
+/// The actions a key can be bound to, with the key that does them by default
+const ACTIONS: &[(&str, &str)] = &[
+    ("save", "ctrl+s"),
+    ("open", "ctrl+o"),
+    ("open fuzzy", "ctrl+p"),
+    ("undo", "ctrl+z"),
+    ("redo", "ctrl+y"),
+    ("copy", "ctrl+c"),
+    ("paste", "ctrl+v"),
+    ("goto", "ctrl+g"),
+    ("find", "ctrl+f"),
+    ("command", "ctrl+e"),
+    ("buffers", "ctrl+b"),
+    ("new buffer", "ctrl+n"),
+    ("next buffer", "ctrl+pagedown"),
+    ("previous buffer", "ctrl+pageup"),
+    ("close", "ctrl+w"),
+    ("quit", "esc"),
+    ("force quit", "ctrl+q"),
+    ("explorer", "alt+e"),
+    ("split", "alt+s"),
+    ("vsplit", "alt+v"),
+    ("next window", "alt+w"),
+    ("close window", "alt+q"),
+    ("match bracket", "ctrl+k"),
+    ("select inside", "alt+i"),
+    ("select around", "alt+a"),
+    ("comment", "ctrl+/"),
+    ("block comment", "alt+/"),
+    ("delete line", "ctrl+h"),
+    ("duplicate line", "alt+d"),
+    ("join lines", "alt+j"),
+    ("move line up", "alt+shift+up"),
+    ("move line down", "alt+shift+down"),
+    ("right", "right"),
+    ("left", "left"),
+    ("up", "up"),
+    ("down", "down"),
+    ("right word", "shift+right"),
+    ("left word", "shift+left"),
+    ("up 5", "shift+up"),
+    ("down 5", "shift+down"),
+    ("right line", "ctrl+right"),
+    ("left line", "ctrl+left"),
+];
+
+///
+/// ### Key bindings, loaded from `keymap.json`
+///
+/// The file is a JSON object binding keys to actions, pressing the key acts
+/// like pressing the key the action has by default. Other keys keep working
+/// as they are:
+///
+/// ```json
+/// {
+///     "ctrl + s": "save",
+///     "alt + x": "force quit",
+///     "shift + up": "up 5"
+/// }
+/// ```
+///
+/// It is read from `$XDG_CONFIG_HOME/pte/keymap.json`, or from `--keymap`.
+///
+pub struct Keymap {
+    remaps: HashMap<Key, Key>,
+}
+
+impl Keymap {
+    pub fn new() -> Self {
+        Self { remaps: HashMap::new() }
+    }
+
+    /// Read a keymap file, collecting an error for every bad binding
+    pub fn load(path: &Path) -> Result<Self, Vec<String>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| vec![format!("{}: {}", path.display(), err)])?;
+        let bindings = parse_object(&contents)
+            .map_err(|(line, err)| vec![format!("{}:{}: {}", path.display(), line, err)])?;
+
+        let mut keymap = Self::new();
+        let mut errors = Vec::new();
+        for (line, key, action) in bindings {
+            let binding = parse_key(&key).and_then(|from| Ok((from, action_key(&action)?)));
+            match binding {
+                Ok((from, to)) => {
+                    keymap.remaps.insert(from, to);
+                }
+                Err(err) => errors.push(format!("{}:{}: {}", path.display(), line, err)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(keymap)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The key to act on when `code` is pressed with `modifiers`
+    pub fn apply(&self, code: KeyCode, modifiers: KeyModifiers) -> Key {
+        // for characters shift is already part of the character ('A' vs 'a')
+        let matched = match code {
+            KeyCode::Char(_) => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        self.remaps.get(&(code, matched)).copied().unwrap_or((code, modifiers))
+    }
+}
+
+/// Where the user's keymap is, next to their config file
+pub fn user_keymap_path() -> Option<PathBuf> {
+    Some(config::user_config_path()?.with_file_name("keymap.json"))
+}
+
+/// The key that does `action` by default
+fn action_key(action: &str) -> Result<Key, String> {
+    let action = action.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    let (_, key) = ACTIONS.iter()
+        .find(|(name, _)| *name == action)
+        .ok_or_else(|| format!("unknown action `{}`", action))?;
+    parse_key(key)
+}
+
+/// Parse a key like `ctrl + s`, `alt+shift+up` or `f5`
+fn parse_key(text: &str) -> Result<Key, String> {
+    let text: String = text.split_whitespace().collect();
+    let mut modifiers = KeyModifiers::NONE;
+    let mut parts: Vec<&str> = text.split('+').collect();
+    // "ctrl++" is ctrl and the plus key
+    if text.ends_with("++") {
+        parts.truncate(parts.len() - 2);
+        parts.push("+");
+    }
+    let name = parts.pop().filter(|name| !name.is_empty())
+        .ok_or_else(|| format!("missing key in `{}`", text))?;
+
+    for modifier in parts {
+        modifiers |= match modifier.to_lowercase().as_str() {
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" | "meta" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return Err(format!("unknown modifier `{}`", modifier)),
+        };
+    }
+
+    let lower = name.to_lowercase();
+    let code = match lower.as_str() {
+        "enter" | "return" => KeyCode::Enter,
+        "esc" | "escape" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "delete" | "del" => KeyCode::Delete,
+        "space" => KeyCode::Char(' '),
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        _ if name.chars().count() == 1 => {
+            let c = name.chars().next().unwrap_or_default();
+            KeyCode::Char(if modifiers.contains(KeyModifiers::SHIFT) { c.to_ascii_uppercase() } else { c })
+        }
+        _ if lower.starts_with('f') => match lower[1..].parse::<u8>() {
+            Ok(n) if (1..=12).contains(&n) => KeyCode::F(n),
+            _ => return Err(format!("unknown key `{}`", name)),
+        },
+        _ => return Err(format!("unknown key `{}`", name)),
+    };
+
+    // see Keymap::apply
+    if matches!(code, KeyCode::Char(_)) {
+        modifiers -= KeyModifiers::SHIFT;
+    }
+    Ok((code, modifiers))
+}
+
+/**
+ * Read a JSON object whose values are all strings
+ *
+ * Returns the pairs in order, with the line each one is on. Errors come
+ * with the line they were found on.
+ */
+fn parse_object(text: &str) -> Result<Vec<(usize, String, String)>, JsonError> {
+    let mut reader = JsonReader { chars: text.chars().collect(), pos: 0 };
+    let mut pairs = Vec::new();
+
+    reader.expect('{')?;
+    if !reader.eat('}') {
+        loop {
+            reader.skip_whitespace();
+            let line = reader.line();
+            let key = reader.string()?;
+            reader.expect(':')?;
+            let value = reader.string()?;
+            pairs.push((line, key, value));
+            if reader.eat('}') {
+                break;
+            }
+            reader.expect(',')?;
+        }
+    }
+    reader.skip_whitespace();
+    if reader.pos < reader.chars.len() {
+        return Err(reader.error("unexpected text after the object"));
+    }
+    Ok(pairs)
+}
+
+struct JsonReader {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl JsonReader {
+    fn line(&self) -> usize {
+        self.chars[..self.pos].iter().filter(|c| **c == '\n').count() + 1
+    }
+
+    fn error(&self, message: &str) -> JsonError {
+        (self.line(), message.to_string())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        let found = self.chars.get(self.pos) == Some(&expected);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        match self.eat(expected) {
+            true => Ok(()),
+            false => Err(self.error(&format!("expected `{}`", expected))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect('"').map_err(|_| self.error("expected a string"))?;
+        let mut out = String::new();
+        loop {
+            let Some(&c) = self.chars.get(self.pos) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escaped = self.chars.get(self.pos).copied();
+                    self.pos += 1;
+                    out.push(match escaped {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let hex: String = self.chars.iter().skip(self.pos).take(4).collect();
+                            self.pos += 4;
+                            u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+                                .ok_or_else(|| self.error("invalid \\u escape"))?
+                        }
+                        Some(c @ ('"' | '\\' | '/')) => c,
+                        _ => return Err(self.error("invalid escape")),
+                    });
+                }
+                '\n' => return Err(self.error("unterminated string")),
+                _ => out.push(c),
+            }
+        }
+    }
+}
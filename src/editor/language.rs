@@ -0,0 +1,195 @@
+use std::path::Path;
+
+/// File extensions (and a few whole file names) for every known filetype
+const FILETYPES: [(&str, &[&str]); 20] = [
+    ("rust", &["rs"]),
+    ("python", &["py", "pyw"]),
+    ("javascript", &["js", "mjs", "cjs", "jsx"]),
+    ("typescript", &["ts", "tsx"]),
+    ("c", &["c", "h"]),
+    ("cpp", &["cpp", "cc", "cxx", "hpp", "hh", "hxx"]),
+    ("go", &["go"]),
+    ("java", &["java"]),
+    ("shell", &["sh", "bash", "zsh"]),
+    ("lua", &["lua"]),
+    ("ruby", &["rb"]),
+    ("html", &["html", "htm"]),
+    ("css", &["css", "scss"]),
+    ("json", &["json"]),
+    ("toml", &["toml"]),
+    ("yaml", &["yaml", "yml"]),
+    ("markdown", &["md", "markdown"]),
+    ("sql", &["sql"]),
+    ("make", &["mk", "Makefile", "makefile", "GNUmakefile"]),
+    ("text", &["txt"]),
+];
+
+/**
+ * Name of the filetype of `filename`, from its extension
+ */
+pub fn filetype(filename: &str) -> Option<&'static str> {
+    let path = Path::new(filename);
+    let name = path.file_name()?.to_str()?;
+    let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+
+    FILETYPES.iter()
+        .find(|(_, patterns)| patterns.iter().any(|pattern| {
+            *pattern == name || extension.as_deref() == Some(*pattern)
+        }))
+        .map(|(filetype, _)| *filetype)
+}
+
+///
+/// ### How a language is indented
+///
+/// Patterns are regexes matched against a line without its indentation, an
+/// empty one never matches. Brackets are handled for every language listed
+/// in `INDENT_RULES`: an open one at the end of a line indents the next, and
+/// text after it lines the next line up with that text.
+///
+pub struct IndentRules {
+    /// The line after one matching this is indented one level more
+    pub increase: &'static str,
+    /// A line matching this goes back a level as it's typed, like `}` or `end`
+    pub decrease: &'static str,
+    /// The line after one matching this goes back a level, like after `return` in Python
+    pub dedent_next: &'static str,
+    /// Lines of `/* */` comments are kept lined up, with their ` * `
+    pub block_comments: bool,
+}
+
+const C_LIKE: IndentRules = IndentRules {
+    increase: r"^(case\b.*|default\s*):\s*$",
+    decrease: r"^[)\]}]",
+    dedent_next: "",
+    block_comments: true,
+};
+
+const BRACKETS: IndentRules = IndentRules {
+    increase: "",
+    decrease: r"^[)\]}]",
+    dedent_next: "",
+    block_comments: false,
+};
+
+const INDENT_RULES: [(&str, IndentRules); 17] = [
+    ("rust", IndentRules { increase: "", ..C_LIKE }),
+    ("javascript", C_LIKE),
+    ("typescript", C_LIKE),
+    ("c", C_LIKE),
+    ("cpp", C_LIKE),
+    ("go", C_LIKE),
+    ("java", C_LIKE),
+    ("css", IndentRules { increase: "", ..C_LIKE }),
+    ("json", BRACKETS),
+    ("toml", BRACKETS),
+    ("sql", BRACKETS),
+    ("python", IndentRules {
+        increase: r":\s*(#.*)?$",
+        decrease: r"^(else|elif\b.*|except\b.*|finally)\s*:$",
+        dedent_next: r"^(return|pass|break|continue|raise)\b",
+        block_comments: false,
+    }),
+    ("ruby", IndentRules {
+        increase: r"^(def|class|module|if|unless|while|until|for|case|begin|else|elsif|when|rescue|ensure)\b|\bdo(\s*\|[^|]*\|)?\s*$|\{\s*(\|[^|]*\|)?\s*$",
+        decrease: r"^(end|else|elsif|when|rescue|ensure)\b|^[)\]}]",
+        ..BRACKETS
+    }),
+    ("lua", IndentRules {
+        increase: r"(\bthen|\bdo|\belse|\brepeat|\bfunction\b.*\))\s*$",
+        decrease: r"^(end|else|elseif|until)\b|^[)\]}]",
+        ..BRACKETS
+    }),
+    ("shell", IndentRules {
+        increase: r"(\bthen|\bdo|\belse|\bin)\s*$",
+        decrease: r"^(fi|done|esac|else|elif)\b|^[)\]}]",
+        ..BRACKETS
+    }),
+    ("yaml", IndentRules { increase: r":\s*(#.*)?$|^-\s*$", decrease: "", ..BRACKETS }),
+    ("html", IndentRules {
+        increase: r"<(html|head|body|div|section|article|nav|header|footer|main|aside|ul|ol|li|table|thead|tbody|tr|td|th|form|select|script|style|template|p)\b[^>]*>\s*$",
+        decrease: r"^</",
+        ..BRACKETS
+    }),
+];
+
+/**
+ * Indentation rules of a filetype, None for ones we only keep the indentation of
+ */
+pub fn indent_rules(filetype: &str) -> Option<&'static IndentRules> {
+    INDENT_RULES.iter()
+        .find(|(name, _)| *name == filetype)
+        .map(|(_, rules)| rules)
+}
+
+/// Brackets and quotes typed in pairs, unless a filetype has its own
+const PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+
+/// Pairs for filetypes that differ from `PAIRS`
+const LANGUAGE_PAIRS: [(&str, &[(char, char)]); 8] = [
+    // ' starts a lifetime as often as a char
+    ("rust", &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')]),
+    ("javascript", &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\''), ('`', '`')]),
+    ("typescript", &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\''), ('`', '`')]),
+    ("go", &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\''), ('`', '`')]),
+    ("shell", &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\''), ('`', '`')]),
+    ("html", &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\''), ('<', '>')]),
+    // apostrophes are too common in prose
+    ("markdown", &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('`', '`')]),
+    ("text", &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')]),
+];
+
+/**
+ * The pairs auto-closed in files of `filetype`
+ */
+pub fn pairs(filetype: Option<&str>) -> &'static [(char, char)] {
+    LANGUAGE_PAIRS.iter()
+        .find(|(name, _)| Some(*name) == filetype)
+        .map_or(PAIRS, |(_, pairs)| pairs)
+}
+
+///
+/// ### Comment syntax of a language
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Comments {
+    /// Starts a comment that runs to the end of the line
+    pub line: Option<&'static str>,
+    /// Start and end of a comment that can span lines
+    pub block: Option<(&'static str, &'static str)>,
+}
+
+const C_COMMENTS: Comments = Comments { line: Some("//"), block: Some(("/*", "*/")) };
+const HASH_COMMENTS: Comments = Comments { line: Some("#"), block: None };
+const MARKUP_COMMENTS: Comments = Comments { line: None, block: Some(("<!--", "-->")) };
+
+const COMMENTS: [(&str, Comments); 19] = [
+    ("rust", C_COMMENTS),
+    ("javascript", C_COMMENTS),
+    ("typescript", C_COMMENTS),
+    ("c", C_COMMENTS),
+    ("cpp", C_COMMENTS),
+    ("go", C_COMMENTS),
+    ("java", C_COMMENTS),
+    ("css", Comments { line: None, block: Some(("/*", "*/")) }),
+    ("python", HASH_COMMENTS),
+    ("shell", HASH_COMMENTS),
+    ("ruby", HASH_COMMENTS),
+    ("yaml", HASH_COMMENTS),
+    ("toml", HASH_COMMENTS),
+    ("make", HASH_COMMENTS),
+    ("lua", Comments { line: Some("--"), block: Some(("--[[", "]]")) }),
+    ("sql", Comments { line: Some("--"), block: Some(("/*", "*/")) }),
+    ("html", MARKUP_COMMENTS),
+    ("markdown", MARKUP_COMMENTS),
+    ("json", Comments { line: None, block: None }),
+];
+
+/**
+ * How comments are written in files of `filetype`, nothing for unknown ones
+ */
+pub fn comments(filetype: Option<&str>) -> Comments {
+    COMMENTS.iter()
+        .find(|(name, _)| Some(*name) == filetype)
+        .map_or(Comments::default(), |(_, comments)| *comments)
+}
@@ -0,0 +1,657 @@
+use encoding_rs::UTF_8;
+use memmap2::Mmap;
+use regex::bytes::RegexBuilder;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::utils::{
+    encoding,
+    files::write_atomic_with,
+    format::{FileFormat, LineEnding},
+};
+use super::editor::Editor;
+
+/// Files at least this big (in MiB) are opened in large file mode
+pub const DEFAULT_THRESHOLD_MB: u64 = 64;
+
+/// Lines loaded into `editor.lines` at a time
+const WINDOW_LINES: usize = 4000;
+
+/// Move the window once the cursor gets this close to its edge
+const EDGE: usize = 500;
+
+/// Bytes the indexer scans before reporting line starts
+const CHUNK: usize = 16 * 1024 * 1024;
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+///
+/// ### An edit to a large file, as kept in its swap file
+///
+/// Lines `start..end` of the file as it was opened are replaced by `lines`.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Patch {
+    pub start: usize,
+    pub end: usize,
+    pub lines: Vec<String>,
+}
+
+/// A run of lines in the document, either still in the file or edited
+#[derive(Clone)]
+enum Segment {
+    /// Lines `start..end` of the file on disk
+    Original { start: usize, end: usize },
+    Edited(Vec<String>),
+}
+
+impl Segment {
+    fn len(&self) -> usize {
+        match self {
+            Segment::Original { start, end } => end - start,
+            Segment::Edited(lines) => lines.len(),
+        }
+    }
+}
+
+///
+/// ### A file too big to load into memory
+///
+/// The file is memory-mapped and a background thread records where every
+/// line starts. Only a window of lines lives in `editor.lines`. When the
+/// cursor nears the edge of the window, the window is written back into
+/// `segments` and a new one is loaded around the cursor.
+///
+/// Saving streams untouched lines straight from the map, so only edited
+/// lines are ever held in memory. Undo only covers the current window.
+///
+pub struct LargeFile {
+    /// The mapped file, which stays the same file if it's replaced on disk
+    file: File,
+    map: Arc<Mmap>,
+    /// Byte offset every line of the file starts at
+    line_starts: Vec<u64>,
+    /// Receives more line starts until the whole file is indexed
+    indexer: Option<Receiver<Vec<u64>>>,
+    /// The whole document, the window's part as it was last stored
+    segments: Vec<Segment>,
+    /// Document line the window starts at
+    pub window_start: usize,
+    /// The window's lines as they were loaded, to tell whether it was edited
+    window_original: Vec<String>,
+}
+
+impl LargeFile {
+    /// Map a file and start indexing it, returning the first window of lines
+    pub fn open(path: &Path) -> io::Result<(Self, Vec<String>, FileFormat)> {
+        let file = File::open(path)?;
+        // SAFETY: the map is only read. If another program truncates the file
+        // while it is open, reading past the new end would fault, which is the
+        // usual caveat of memory-mapped files.
+        let map = Arc::new(unsafe { Mmap::map(&file)? });
+
+        let bom = map.starts_with(BOM);
+        let first = if bom { BOM.len() } else { 0 };
+
+        // CRLF if most of the first lines use it
+        let sample = &map[..map.len().min(64 * 1024)];
+        let newlines = sample.iter().filter(|b| **b == b'\n').count();
+        let crlf = sample.windows(2).filter(|w| w == b"\r\n").count();
+        let mut format = FileFormat::new();
+        format.line_ending = if crlf * 2 > newlines { LineEnding::CrLf } else { LineEnding::Lf };
+        format.trailing_newline = map.ends_with(b"\n");
+        format.bom = bom;
+
+        let indexer = spawn_indexer(map.clone(), first);
+        let mut large = Self {
+            file,
+            map,
+            line_starts: vec![first as u64],
+            indexer: Some(indexer),
+            segments: Vec::new(),
+            window_start: 0,
+            window_original: Vec::new(),
+        };
+
+        // give the indexer a moment, so the first window isn't empty
+        if let Some(Ok(starts)) = large.indexer.as_ref().map(|rx| rx.recv_timeout(Duration::from_millis(300))) {
+            large.line_starts.extend(starts);
+            large.grow(0);
+        }
+        large.poll();
+
+        let lines = large.load(0);
+        Ok((large, lines, format))
+    }
+
+    /// Size of the file as it was mapped
+    pub fn size(&self) -> u64 {
+        self.map.len() as u64
+    }
+
+    /**
+     * Whether all of the map can still be read
+     *
+     * Another program truncating the file in place would make reading the
+     * lost part of the map crash the editor. A file replaced by a new one
+     * is fine, the map still holds the old one.
+     */
+    pub fn intact(&self) -> bool {
+        self.file.metadata().is_ok_and(|metadata| metadata.len() >= self.map.len() as u64)
+    }
+
+    /**
+     * The edits made to the file, with `window` as the current window
+     */
+    pub fn patches(&mut self, window: &[String]) -> Vec<Patch> {
+        self.store(window);
+        let mut patches = Vec::new();
+        let mut next = 0;
+        let mut edited = Vec::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Original { start, end } => {
+                    if *start != next || !edited.is_empty() {
+                        patches.push(Patch { start: next, end: *start, lines: std::mem::take(&mut edited) });
+                    }
+                    next = *end;
+                }
+                Segment::Edited(lines) => edited.extend(lines.iter().cloned()),
+            }
+        }
+        let known = self.known_lines();
+        if next != known || !edited.is_empty() {
+            patches.push(Patch { start: next, end: known, lines: edited });
+        }
+        patches.into_iter().filter_map(|patch| self.trim(patch)).collect()
+    }
+
+    /// Leave the lines a patch doesn't change out of it, None if that's all of them
+    fn trim(&self, mut patch: Patch) -> Option<Patch> {
+        let prefix = (patch.start..patch.end).zip(&patch.lines)
+            .take_while(|(line, text)| self.line_text(*line) == **text)
+            .count();
+        patch.start += prefix;
+        patch.lines.drain(..prefix);
+
+        let suffix = (patch.start..patch.end).rev().zip(patch.lines.iter().rev())
+            .take_while(|(line, text)| self.line_text(*line) == **text)
+            .count();
+        patch.end -= suffix;
+        patch.lines.truncate(patch.lines.len() - suffix);
+
+        (patch.start != patch.end || !patch.lines.is_empty()).then_some(patch)
+    }
+
+    /**
+     * Make the edits of a swap file again, returning the new window
+     *
+     * None if they don't fit the file, which is left as it was.
+     */
+    pub fn apply_patches(&mut self, patches: &[Patch]) -> Option<Vec<String>> {
+        self.finish_indexing();
+        let total = self.known_lines();
+        let mut segments = Vec::new();
+        let mut next = 0;
+        for patch in patches {
+            if patch.start < next || patch.end < patch.start || patch.end > total {
+                return None;
+            }
+            if patch.start > next {
+                segments.push(Segment::Original { start: next, end: patch.start });
+            }
+            if !patch.lines.is_empty() {
+                segments.push(Segment::Edited(patch.lines.clone()));
+            }
+            next = patch.end;
+        }
+        if next < total {
+            segments.push(Segment::Original { start: next, end: total });
+        }
+
+        self.segments = segments;
+        self.window_original = Vec::new();
+        Some(self.load(self.window_start))
+    }
+
+    /// Lines `start..end` of the file as it was opened
+    pub fn original(&self, start: usize, end: usize) -> Vec<String> {
+        (start..end.min(self.line_starts.len())).map(|line| self.line_text(line)).collect()
+    }
+
+    fn indexing(&self) -> bool {
+        self.indexer.is_some()
+    }
+
+    /// Lines of the file whose end is known
+    fn known_lines(&self) -> usize {
+        if self.indexing() {
+            self.line_starts.len() - 1
+        } else {
+            self.line_starts.len()
+        }
+    }
+
+    /// Take in line starts found by the indexer
+    ///
+    /// Returns true if more of the file became available
+    fn poll(&mut self) -> bool {
+        let before = self.known_lines();
+        if let Some(receiver) = &self.indexer {
+            loop {
+                match receiver.try_recv() {
+                    Ok(starts) => self.line_starts.extend(starts),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.indexer = None;
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.grow(before)
+    }
+
+    /// Block until the whole file is indexed
+    fn finish_indexing(&mut self) {
+        let before = self.known_lines();
+        if let Some(receiver) = self.indexer.take() {
+            for starts in receiver {
+                self.line_starts.extend(starts);
+            }
+        }
+        self.grow(before);
+    }
+
+    /// Add lines that became known since there were `before` of them to the document
+    fn grow(&mut self, before: usize) -> bool {
+        let after = self.known_lines();
+        if after == before {
+            return false;
+        }
+        match self.segments.last_mut() {
+            Some(Segment::Original { end, .. }) if *end == before => *end = after,
+            _ => self.segments.push(Segment::Original { start: before, end: after }),
+        }
+        true
+    }
+
+    fn line_end(&self, line: usize) -> usize {
+        match self.line_starts.get(line + 1) {
+            Some(start) => *start as usize,
+            None => self.map.len(),
+        }
+    }
+
+    /// Bytes of a line of the file, without its line ending
+    fn line_bytes(&self, line: usize) -> &[u8] {
+        let bytes = &self.map[self.line_starts[line] as usize..self.line_end(line)];
+        let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+        bytes.strip_suffix(b"\r").unwrap_or(bytes)
+    }
+
+    fn line_text(&self, line: usize) -> String {
+        encoding::decode(self.line_bytes(line), UTF_8).0
+    }
+
+    /// Number of lines in the document, with `window_len` lines in the window
+    pub fn total_lines(&self, window_len: usize) -> usize {
+        let stored: usize = self.segments.iter().map(Segment::len).sum();
+        stored - self.window_original.len() + window_len
+    }
+
+    /// Make sure a segment starts at document line `line`, returning its index
+    fn split_at(&mut self, line: usize) -> usize {
+        let mut pos = 0;
+        for i in 0..self.segments.len() {
+            let len = self.segments[i].len();
+            if line == pos {
+                return i;
+            }
+            if line < pos + len {
+                let offset = line - pos;
+                let second = match &mut self.segments[i] {
+                    Segment::Original { start, end } => {
+                        let second = Segment::Original { start: *start + offset, end: *end };
+                        *end = *start + offset;
+                        second
+                    }
+                    Segment::Edited(lines) => Segment::Edited(lines.split_off(offset)),
+                };
+                self.segments.insert(i + 1, second);
+                return i + 1;
+            }
+            pos += len;
+        }
+        self.segments.len()
+    }
+
+    /// Load the lines around document line `start` as the window
+    fn load(&mut self, start: usize) -> Vec<String> {
+        let total = self.total_lines(self.window_original.len());
+        let start = start.min(total.saturating_sub(1));
+        let end = (start + WINDOW_LINES).min(total);
+
+        let first = self.split_at(start);
+        let last = self.split_at(end);
+        let mut lines = Vec::with_capacity(end - start);
+        for segment in &self.segments[first..last] {
+            match segment {
+                Segment::Original { start, end } => lines.extend((*start..*end).map(|line| self.line_text(line))),
+                Segment::Edited(edited) => lines.extend(edited.iter().cloned()),
+            }
+        }
+
+        self.window_start = start;
+        self.window_original = lines.clone();
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines
+    }
+
+    /// Put the (possibly edited) window back into the document
+    fn store(&mut self, lines: &[String]) {
+        // an empty window still shows one empty line
+        let untouched = self.window_original.is_empty() && lines.len() == 1 && lines[0].is_empty();
+        if untouched || lines == self.window_original {
+            return;
+        }
+        let first = self.split_at(self.window_start);
+        let last = self.split_at(self.window_start + self.window_original.len());
+        self.segments.splice(first..last, [Segment::Edited(lines.to_vec())]);
+        self.window_original = lines.to_vec();
+    }
+
+    /// Short description for the status area
+    pub fn status(&self, window_len: usize) -> String {
+        if self.indexing() {
+            let percent = self.line_starts.last().copied().unwrap_or(0) * 100 / self.map.len().max(1) as u64;
+            format!("large, indexing {}%", percent)
+        } else {
+            format!("large, {} lines", self.total_lines(window_len))
+        }
+    }
+
+    /// Find the first match of `query` after (line, col), wrapping around
+    ///
+    /// Case insensitive, like the regular finder.
+    fn find(&mut self, query: &str, line: usize, col: usize) -> Option<(usize, usize)> {
+        let regex = RegexBuilder::new(&regex::escape(query)).case_insensitive(true).build().ok()?;
+        let split = self.split_at(line);
+
+        // search from the cursor to the end, then from the top to the cursor
+        let order: Vec<usize> = (split..self.segments.len()).chain(0..split).collect();
+        let starts: Vec<usize> = self.segments.iter()
+            .scan(0, |pos, segment| {
+                let start = *pos;
+                *pos += segment.len();
+                Some(start)
+            })
+            .collect();
+
+        for i in order {
+            let skip_before = (i == split).then_some(col + 1);
+            match &self.segments[i] {
+                Segment::Original { start, end } => {
+                    let from = self.line_starts[*start] as usize;
+                    let to = self.line_end(end - 1);
+                    for found in regex.find_iter(&self.map[from..to]) {
+                        let position = (from + found.start()) as u64;
+                        let file_line = self.line_starts.partition_point(|s| *s <= position) - 1;
+                        let found_col = (position - self.line_starts[file_line]) as usize;
+                        let doc_line = starts[i] + file_line - start;
+                        if doc_line == line && skip_before.is_some_and(|skip| found_col < skip) {
+                            continue;
+                        }
+                        return Some((doc_line, found_col));
+                    }
+                }
+                Segment::Edited(lines) => {
+                    for (offset, text) in lines.iter().enumerate() {
+                        let doc_line = starts[i] + offset;
+                        let from = if doc_line == line { skip_before.unwrap_or(0).min(text.len()) } else { 0 };
+                        if let Some(found) = regex.find_at(text.as_bytes(), from) {
+                            return Some((doc_line, found.start()));
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+fn spawn_indexer(map: Arc<Mmap>, first: usize) -> Receiver<Vec<u64>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut offset = first;
+        while offset < map.len() {
+            let end = (offset + CHUNK).min(map.len());
+            let starts: Vec<u64> = map[offset..end].iter()
+                .enumerate()
+                .filter(|(i, byte)| **byte == b'\n' && offset + i + 1 < map.len())
+                .map(|(i, _)| (offset + i + 1) as u64)
+                .collect();
+            if sender.send(starts).is_err() {
+                return;
+            }
+            offset = end;
+        }
+    });
+    receiver
+}
+
+/// Move the window so it starts at document line `start`, keeping cursors in place
+///
+/// Returns false if the file can't be read anymore, the window stays then.
+fn move_window(editor: &mut Editor, start: usize) -> bool {
+    let Some(large) = editor.large.as_mut().filter(|large| large.intact()) else {
+        return false;
+    };
+    large.store(&editor.lines);
+    let old_start = large.window_start;
+    editor.lines = large.load(start);
+    let new_start = large.window_start;
+
+    let last = editor.lines.len() - 1;
+    let relocate = |line: u16| (old_start + line as usize).saturating_sub(new_start).min(last) as u16;
+    editor.cursors.truncate(1);
+    editor.cursors[0].line = relocate(editor.cursors[0].line);
+    editor.scroll = (old_start + editor.scroll).saturating_sub(new_start);
+    editor.marks.retain(|_, line| {
+        let doc_line = old_start + *line as usize;
+        if doc_line < new_start || doc_line - new_start > last {
+            return false;
+        }
+        *line = (doc_line - new_start) as u16;
+        true
+    });
+
+    // undo snapshots only cover the window they were taken in
+    editor.history.clear();
+    true
+}
+
+/**
+ * Pick up indexing progress and keep the window around the cursor
+ */
+pub fn update(editor: &mut Editor) {
+    let Some(large) = editor.large.as_mut() else {
+        return;
+    };
+
+    // the window may have started out short while the file was being indexed
+    if large.poll() && editor.lines.len() < WINDOW_LINES && editor.lines == large.window_original {
+        let start = large.window_start;
+        move_window(editor, start);
+        return;
+    }
+
+    let line = editor.cursors[0].line as usize;
+    let window_end = large.window_start + editor.lines.len();
+    let near_end = line + EDGE >= editor.lines.len() && window_end < large.total_lines(editor.lines.len());
+    let near_start = line < EDGE && large.window_start > 0;
+    if near_end || near_start {
+        let start = (large.window_start + line).saturating_sub(WINDOW_LINES / 2);
+        move_window(editor, start);
+    }
+}
+
+/**
+ * Move the cursor to a document line, loading the window around it
+ */
+pub fn goto(editor: &mut Editor, line: usize, col: usize) {
+    let Some(large) = editor.large.as_ref() else {
+        return;
+    };
+    let line = line.min(large.total_lines(editor.lines.len()).saturating_sub(1));
+    let start = large.window_start;
+    let outside = line < start || line >= start + editor.lines.len();
+    if outside && !move_window(editor, line.saturating_sub(WINDOW_LINES / 2)) {
+        editor.notif_text = String::from("The file was truncated on disk, only the loaded lines are left");
+        return;
+    }
+
+    let start = editor.large.as_ref().map_or(0, |large| large.window_start);
+    editor.cursors.truncate(1);
+    editor.cursors[0].line = (line - start) as u16;
+    editor.cursors[0].col = col.min(editor.lines[line - start].len()) as u16;
+}
+
+/**
+ * Search the whole file for `query`, starting after the cursor
+ */
+pub fn find(editor: &mut Editor, query: &str) -> Option<usize> {
+    let large = editor.large.as_mut().filter(|large| large.intact())?;
+    large.store(&editor.lines);
+
+    let line = large.window_start + editor.cursors[0].line as usize;
+    let (found_line, col) = large.find(query, line, editor.cursors[0].col as usize)?;
+    goto(editor, found_line, col);
+    Some(found_line)
+}
+
+/**
+ * Write the document to `path`, copying untouched lines from the map
+ */
+pub fn save(editor: &mut Editor, path: &str) -> io::Result<()> {
+    let Some(large) = editor.large.as_mut() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a large file"));
+    };
+    if !large.intact() {
+        return Err(io::Error::other("the file was truncated on disk, its unedited lines are lost"));
+    }
+    large.finish_indexing();
+    large.store(&editor.lines);
+
+    let line_ending = editor.format.line_ending.as_str().as_bytes();
+    let mut new_starts = Vec::new();
+    write_atomic_with(Path::new(path), |file| {
+        let mut out = BufWriter::new(file);
+        let mut written = 0u64;
+        if editor.format.bom {
+            out.write_all(BOM)?;
+            written += BOM.len() as u64;
+        }
+
+        // every line gets the buffer's line ending, unedited ones too
+        let count = large.segments.len();
+        let mut write_line = |bytes: &[u8], last: bool| -> io::Result<()> {
+            new_starts.push(written);
+            out.write_all(bytes)?;
+            written += bytes.len() as u64;
+            if !last || editor.format.trailing_newline {
+                out.write_all(line_ending)?;
+                written += line_ending.len() as u64;
+            }
+            Ok(())
+        };
+        for (i, segment) in large.segments.iter().enumerate() {
+            let last_segment = i + 1 == count;
+            match segment {
+                Segment::Original { start, end } => {
+                    for line in *start..*end {
+                        write_line(large.line_bytes(line), last_segment && line + 1 == *end)?;
+                    }
+                }
+                Segment::Edited(lines) => {
+                    for (j, line) in lines.iter().enumerate() {
+                        let bytes = encoding::encode(line, UTF_8)
+                            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                        write_line(&bytes, last_segment && j + 1 == lines.len())?;
+                    }
+                }
+            }
+        }
+        out.flush()
+    })?;
+
+    // when saving over the file, map the new one so edits don't pile up
+    if path == editor.filename {
+        let file = File::open(path)?;
+        // SAFETY: see LargeFile::open
+        large.map = Arc::new(unsafe { Mmap::map(&file)? });
+        large.file = file;
+        if new_starts.is_empty() {
+            new_starts.push(if editor.format.bom { BOM.len() as u64 } else { 0 });
+        }
+        large.segments = vec![Segment::Original { start: 0, end: new_starts.len() }];
+        large.line_starts = new_starts;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    /// Open `contents` as a large file in a new editor
+    fn open_editor(name: &str, contents: &str) -> (Editor, String) {
+        let path = env::temp_dir().join(format!("pte-large-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        let (large, lines, format) = LargeFile::open(&path).unwrap();
+        let mut editor = Editor::new();
+        editor.filename = path.to_string_lossy().to_string();
+        editor.large = Some(large);
+        editor.lines = lines;
+        editor.format = format;
+        (editor, path.to_string_lossy().to_string())
+    }
+
+    fn save_to_string(editor: &mut Editor, path: &str) -> String {
+        let out = format!("{}.out", path);
+        save(editor, &out).unwrap();
+        let saved = fs::read_to_string(&out).unwrap();
+        fs::remove_file(&out).unwrap();
+        fs::remove_file(path).unwrap();
+        saved
+    }
+
+    #[test]
+    fn appends_after_a_missing_final_newline() {
+        let (mut editor, path) = open_editor("append", "a\nb");
+        let large = editor.large.as_mut().unwrap();
+        let window = large.apply_patches(&[Patch { start: 2, end: 2, lines: vec![String::from("c")] }]);
+        editor.lines = window.unwrap();
+        assert_eq!(save_to_string(&mut editor, &path), "a\nb\nc");
+    }
+
+    #[test]
+    fn converts_line_endings_of_unedited_lines() {
+        let (mut editor, path) = open_editor("dos", "a\nb\r\nc\n");
+        editor.format.line_ending = LineEnding::CrLf;
+        assert_eq!(save_to_string(&mut editor, &path), "a\r\nb\r\nc\r\n");
+    }
+}
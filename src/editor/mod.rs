@@ -1,6 +1,20 @@
+#[allow(clippy::module_inception)]
 pub mod editor;
 pub mod cursor;
 pub mod input;
 pub mod commands;
 pub mod finder;
-pub mod history;
\ No newline at end of file
+pub mod history;
+pub mod ex;
+pub mod picker;
+pub mod completion;
+pub mod buffer;
+pub mod window;
+pub mod explorer;
+pub mod large;
+pub mod keymap;
+pub mod language;
+pub mod indent;
+pub mod pairs;
+pub mod brackets;
+pub mod comment;
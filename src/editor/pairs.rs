@@ -0,0 +1,35 @@
+/// Characters a bracket can be closed in front of, besides whitespace and the end of the line
+const CLOSE_BEFORE: &str = ")]}>,;:";
+
+/// The pair `c` opens
+pub fn opened_by(pairs: &[(char, char)], c: char) -> Option<(char, char)> {
+    pairs.iter().find(|(open, _)| *open == c).copied()
+}
+
+/**
+ * Whether typing the opening half of `pair` between `before` and `after`
+ * should type the closing half too
+ *
+ * Only in front of whitespace or something that ends an expression, so
+ * typing `(` before a word doesn't close it right away. Quotes aren't
+ * paired after a word, where they're more likely an apostrophe.
+ */
+pub fn closes(pair: (char, char), before: &str, after: &str) -> bool {
+    let fits = after.chars().next().is_none_or(|next| next.is_whitespace() || CLOSE_BEFORE.contains(next));
+    let quote = pair.0 == pair.1;
+    let after_word = before.chars().next_back().is_some_and(|prev| prev.is_alphanumeric() || prev == '_' || prev == pair.0);
+    fits && !(quote && after_word)
+}
+
+/// Whether typing `c` in front of `after` should step over the closing character already there
+pub fn steps_over(pairs: &[(char, char)], c: char, after: &str) -> bool {
+    pairs.iter().any(|(_, close)| *close == c) && after.starts_with(c)
+}
+
+/// Whether backspace between `before` and `after` deletes an empty pair, like `(|)`
+pub fn empty_pair(pairs: &[(char, char)], before: &str, after: &str) -> bool {
+    let (Some(open), Some(close)) = (before.chars().next_back(), after.chars().next()) else {
+        return false;
+    };
+    pairs.contains(&(open, close))
+}
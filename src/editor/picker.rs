@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Read,
+    path::{Component, Path, PathBuf},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+/// Directories that are never worth indexing
+const IGNORED_DIRS: [&str; 3] = ["target", "node_modules", "__pycache__"];
+const MAX_FILES: usize = 50_000;
+const BATCH_SIZE: usize = 256;
+const PREVIEW_LINES: usize = 200;
+
+///
+/// ### Fuzzy file picker
+///
+/// Files are indexed on a background thread and streamed back in batches,
+/// so the picker is usable immediately, even in large trees.
+///
+pub struct FilePicker {
+    pub query: String,
+    pub files: Vec<String>,
+    /// Indices into `files`, best match first
+    pub matches: Vec<usize>,
+    pub selected: usize,
+    pub indexing: bool,
+    receiver: Option<Receiver<Vec<String>>>,
+    previews: HashMap<String, Vec<String>>,
+    /// Directory being indexed, `files` are relative to it
+    root: PathBuf,
+}
+
+impl FilePicker {
+    pub fn new() -> Self {
+        Self {
+            query: String::new(),
+            files: Vec::new(),
+            matches: Vec::new(),
+            selected: 0,
+            indexing: false,
+            receiver: None,
+            previews: HashMap::new(),
+            root: PathBuf::new(),
+        }
+    }
+
+    /// Start indexing `root` in the background, dropping any previous index
+    pub fn open(&mut self, root: &Path) {
+        *self = Self::new();
+        self.root = root.to_path_buf();
+
+        let (sender, receiver) = mpsc::channel();
+        let root = root.to_path_buf();
+        thread::spawn(move || {
+            let mut batch = Vec::new();
+            let mut count = 0;
+            let mut stack = vec![root.clone()];
+
+            while let Some(dir) = stack.pop() {
+                let entries = match fs::read_dir(&dir) {
+                    Ok(entries) => entries,
+                    Err(_) => continue,
+                };
+
+                for entry in entries.flatten() {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    if name.starts_with('.') {
+                        continue;
+                    }
+
+                    let path = entry.path();
+                    let file_type = match entry.file_type() {
+                        Ok(file_type) => file_type,
+                        Err(_) => continue,
+                    };
+
+                    if file_type.is_dir() {
+                        if !IGNORED_DIRS.contains(&name.as_str()) {
+                            stack.push(path);
+                        }
+                        continue;
+                    }
+
+                    let relative = path.strip_prefix(&root).unwrap_or(&path);
+                    batch.push(relative.to_string_lossy().replace('\\', "/"));
+                    count += 1;
+
+                    if batch.len() >= BATCH_SIZE && sender.send(std::mem::take(&mut batch)).is_err() {
+                        return;
+                    }
+                    if count >= MAX_FILES {
+                        let _ = sender.send(batch);
+                        return;
+                    }
+                }
+            }
+
+            let _ = sender.send(batch);
+        });
+
+        self.receiver = Some(receiver);
+        self.indexing = true;
+    }
+
+    /// Pull in any files the indexer has found since the last call
+    ///
+    /// Returns true if the file list changed
+    pub fn poll(&mut self, recent: &[String]) -> bool {
+        let receiver = match &self.receiver {
+            Some(receiver) => receiver,
+            None => return false,
+        };
+
+        let mut changed = false;
+        let mut finished = false;
+        loop {
+            match receiver.try_recv() {
+                Ok(batch) => {
+                    self.files.extend(batch);
+                    changed = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    finished = true;
+                    break;
+                }
+            }
+        }
+
+        if finished {
+            self.indexing = false;
+            self.receiver = None;
+        }
+
+        if changed {
+            self.update_matches(recent);
+        }
+        changed
+    }
+
+    /// Re-rank all files against the current query
+    ///
+    /// `recent` is the list of recently opened files, most recent last
+    pub fn update_matches(&mut self, recent: &[String]) {
+        // recent files are named as they were opened, `./src/x.rs` or absolute
+        let recent: Vec<String> = recent.iter().filter_map(|file| relative_to(&self.root, file)).collect();
+        let mut scored: Vec<(i64, usize)> = self.files.iter()
+            .enumerate()
+            .filter_map(|(i, file)| {
+                let score = fuzzy_score(&self.query, file)?;
+                let recency = match recent.iter().rposition(|r| r == file) {
+                    Some(pos) => 50 + (pos as i64) * 5,
+                    None => 0,
+                };
+                Some((score + recency, i))
+            })
+            .collect();
+
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| self.files[a.1].cmp(&self.files[b.1])));
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+
+        if self.selected >= self.matches.len() {
+            self.selected = self.matches.len().saturating_sub(1);
+        }
+    }
+
+    pub fn next(&mut self) {
+        if self.selected + 1 < self.matches.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn prev(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn selected_file(&self) -> Option<&String> {
+        self.matches.get(self.selected).map(|i| &self.files[*i])
+    }
+
+    /// First lines of the highlighted file, cached per path
+    pub fn preview(&mut self) -> Vec<String> {
+        let file = match self.selected_file() {
+            Some(file) => file.clone(),
+            None => return Vec::new(),
+        };
+
+        self.previews.entry(file.clone())
+            .or_insert_with(|| read_preview(&file))
+            .clone()
+    }
+}
+
+/// `path` relative to `root` with `/` separators, like the indexed files, None if it's outside
+fn relative_to(root: &Path, path: &str) -> Option<String> {
+    let mut normal = PathBuf::new();
+    for component in root.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normal.pop();
+            }
+            other => normal.push(other),
+        }
+    }
+    let relative = normal.strip_prefix(root).ok()?;
+    Some(relative.to_string_lossy().replace('\\', "/"))
+}
+
+fn read_preview(path: &str) -> Vec<String> {
+    let mut buffer = Vec::new();
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(_) => return vec![String::from("<unable to read file>")],
+    };
+
+    // only ever read the start of the file, previews are for glancing
+    if file.take(64 * 1024).read_to_end(&mut buffer).is_err() {
+        return vec![String::from("<unable to read file>")];
+    }
+    if buffer.contains(&0) {
+        return vec![String::from("<binary file>")];
+    }
+
+    String::from_utf8_lossy(&buffer)
+        .lines()
+        .take(PREVIEW_LINES)
+        .map(|line| line.replace('\t', "    "))
+        .collect()
+}
+
+///
+/// ### Score how well `query` fuzzy matches `candidate`
+///
+/// All query characters must appear in order (case insensitive).
+/// Consecutive matches, matches at word boundaries and matches in the
+/// file name score higher; longer paths score slightly lower.
+/// Returns None if there is no match.
+///
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    if query.is_empty() {
+        return Some(-(candidate.len() as i64));
+    }
+
+    let candidate_chars: Vec<char> = candidate.chars().collect();
+    let file_name_start = candidate.rfind('/').map(|i| candidate[..=i].chars().count()).unwrap_or(0);
+
+    let mut score = 0;
+    let mut index = 0;
+    let mut last_match: Option<usize> = None;
+
+    for q in query.chars() {
+        if q == ' ' {
+            continue;
+        }
+        let q = q.to_ascii_lowercase();
+
+        let mut found = false;
+        while index < candidate_chars.len() {
+            let c = candidate_chars[index];
+            if c.to_ascii_lowercase() == q {
+                score += 10;
+
+                if last_match.is_some_and(|last| last + 1 == index) {
+                    score += 15;
+                }
+
+                let boundary = index == 0 || matches!(candidate_chars[index - 1], '/' | '_' | '-' | '.' | ' ');
+                if boundary {
+                    score += 20;
+                }
+                if index >= file_name_start {
+                    score += 5;
+                }
+
+                last_match = Some(index);
+                index += 1;
+                found = true;
+                break;
+            }
+            index += 1;
+        }
+
+        if !found {
+            return None;
+        }
+    }
+
+    Some(score - candidate_chars.len() as i64 / 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_recent_files_like_the_index() {
+        let root = Path::new("/home/me/project");
+        assert_eq!(relative_to(root, "./src/main.rs").as_deref(), Some("src/main.rs"));
+        assert_eq!(relative_to(root, "/home/me/project/src/main.rs").as_deref(), Some("src/main.rs"));
+        assert_eq!(relative_to(root, "docs/../src/main.rs").as_deref(), Some("src/main.rs"));
+        assert_eq!(relative_to(root, "../other/main.rs"), None);
+    }
+
+    #[test]
+    fn ranks_recent_files_first() {
+        let mut picker = FilePicker::new();
+        picker.root = PathBuf::from("/home/me/project");
+        picker.files = vec![String::from("a.rs"), String::from("b.rs")];
+        picker.update_matches(&[String::from("/home/me/project/b.rs")]);
+        assert_eq!(picker.selected_file().map(String::as_str), Some("b.rs"));
+    }
+}
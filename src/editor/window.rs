@@ -0,0 +1,316 @@
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use std::collections::HashMap;
+
+use super::cursor::Cursor;
+
+const MIN_RATIO: u16 = 10;
+const MAX_RATIO: u16 = 90;
+
+///
+/// ### A window onto a buffer
+///
+/// Every view has its own cursors and scroll position, so the same buffer
+/// can be shown twice at different places. The focused view's state lives
+/// on the `Editor` while it is focused, like the active buffer does.
+///
+#[derive(Clone)]
+pub struct View {
+    pub buffer: usize,
+    pub cursors: Vec<Cursor>,
+    pub scroll: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SplitDirection {
+    /// One view above the other
+    Horizontal,
+    /// Views side by side
+    Vertical,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FocusDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+enum Node {
+    Leaf(usize),
+    Split {
+        direction: SplitDirection,
+        /// Percentage of the space given to `first`
+        ratio: u16,
+        first: Box<Node>,
+        second: Box<Node>,
+    },
+}
+
+///
+/// ### Tree of split views in the editor area
+///
+pub struct WindowManager {
+    root: Node,
+    pub views: HashMap<usize, View>,
+    pub focused: usize,
+    next_id: usize,
+    /// Where each view was drawn last frame, used for directional focus
+    pub rects: Vec<(usize, Rect)>,
+}
+
+impl WindowManager {
+    pub fn new() -> Self {
+        let mut views = HashMap::new();
+        views.insert(0, View { buffer: 0, cursors: vec![Cursor::new()], scroll: 0 });
+
+        Self {
+            root: Node::Leaf(0),
+            views,
+            focused: 0,
+            next_id: 1,
+            rects: Vec::new(),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.views.len()
+    }
+
+    pub fn focused_view_mut(&mut self) -> &mut View {
+        self.views.get_mut(&self.focused).expect("focused view exists")
+    }
+
+    /// Split the focused view in two, the new view goes right of / below it
+    ///
+    /// Returns the id of the new view
+    pub fn split(&mut self, direction: SplitDirection, view: View) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.views.insert(id, view);
+
+        let focused = self.focused;
+        if let Some(leaf) = find_leaf(&mut self.root, focused) {
+            *leaf = Node::Split {
+                direction,
+                ratio: 50,
+                first: Box::new(Node::Leaf(focused)),
+                second: Box::new(Node::Leaf(id)),
+            };
+        }
+        id
+    }
+
+    /// Close the focused view, its sibling takes over the space
+    ///
+    /// Returns the id of the view that should be focused next,
+    /// or None if this is the only view
+    pub fn close(&mut self) -> Option<usize> {
+        if self.views.len() == 1 {
+            return None;
+        }
+
+        let focused = self.focused;
+        let next = remove_leaf(&mut self.root, focused)?;
+        self.views.remove(&focused);
+        Some(next)
+    }
+
+    /// Close every view except the focused one
+    pub fn only(&mut self) {
+        let focused = self.focused;
+        self.views.retain(|id, _| *id == focused);
+        self.root = Node::Leaf(focused);
+    }
+
+    /// Grow (positive) or shrink the focused view inside its parent split
+    pub fn resize(&mut self, delta: i16) -> bool {
+        let focused = self.focused;
+        resize_parent(&mut self.root, focused, delta)
+    }
+
+    /// Give every view the same share of its direction
+    pub fn equalize(&mut self) {
+        equalize(&mut self.root);
+    }
+
+    /// Compute the area of every view
+    pub fn layout(&self, area: Rect) -> Vec<(usize, Rect)> {
+        let mut out = Vec::new();
+        layout(&self.root, area, &mut out);
+        out
+    }
+
+    /// Id of the view after the focused one, in layout order
+    pub fn next(&self) -> usize {
+        let mut order = Vec::new();
+        leaves(&self.root, &mut order);
+        let index = order.iter().position(|id| *id == self.focused).unwrap_or(0);
+        order[(index + 1) % order.len()]
+    }
+
+    /// The closest view in `direction` from the focused one, based on the last layout
+    pub fn neighbour(&self, direction: FocusDirection) -> Option<usize> {
+        let current = self.rects.iter().find(|(id, _)| *id == self.focused)?.1;
+
+        self.rects.iter()
+            .filter(|(id, _)| *id != self.focused)
+            .filter(|(_, r)| match direction {
+                FocusDirection::Left => r.x + r.width <= current.x && overlaps(r.y, r.height, current.y, current.height),
+                FocusDirection::Right => r.x >= current.x + current.width && overlaps(r.y, r.height, current.y, current.height),
+                FocusDirection::Up => r.y + r.height <= current.y && overlaps(r.x, r.width, current.x, current.width),
+                FocusDirection::Down => r.y >= current.y + current.height && overlaps(r.x, r.width, current.x, current.width),
+            })
+            .min_by_key(|(_, r)| {
+                let dx = (r.x as i32 - current.x as i32).abs();
+                let dy = (r.y as i32 - current.y as i32).abs();
+                dx + dy
+            })
+            .map(|(id, _)| *id)
+    }
+
+    /// Fix up buffer indices after buffer `removed` was closed
+    ///
+    /// Views that showed it switch to `replacement` (an index after removal)
+    pub fn buffer_removed(&mut self, removed: usize, replacement: usize) {
+        for view in self.views.values_mut() {
+            if view.buffer == removed {
+                view.buffer = replacement;
+            } else if view.buffer > removed {
+                view.buffer -= 1;
+            }
+        }
+    }
+
+    /// Fix up buffer indices after two buffers were reordered
+    pub fn buffers_swapped(&mut self, a: usize, b: usize) {
+        for view in self.views.values_mut() {
+            if view.buffer == a {
+                view.buffer = b;
+            } else if view.buffer == b {
+                view.buffer = a;
+            }
+        }
+    }
+}
+
+fn overlaps(a_start: u16, a_len: u16, b_start: u16, b_len: u16) -> bool {
+    a_start < b_start + b_len && b_start < a_start + a_len
+}
+
+fn find_leaf(node: &mut Node, id: usize) -> Option<&mut Node> {
+    match node {
+        Node::Leaf(leaf) if *leaf == id => Some(node),
+        Node::Leaf(_) => None,
+        Node::Split { first, second, .. } => {
+            if contains(first, id) {
+                find_leaf(first, id)
+            } else {
+                find_leaf(second, id)
+            }
+        }
+    }
+}
+
+fn contains(node: &Node, id: usize) -> bool {
+    match node {
+        Node::Leaf(leaf) => *leaf == id,
+        Node::Split { first, second, .. } => contains(first, id) || contains(second, id),
+    }
+}
+
+fn first_leaf(node: &Node) -> usize {
+    match node {
+        Node::Leaf(id) => *id,
+        Node::Split { first, .. } => first_leaf(first),
+    }
+}
+
+/// Remove a leaf, replacing its parent split with the sibling
+///
+/// Returns a view from the sibling, to move focus to
+fn remove_leaf(node: &mut Node, id: usize) -> Option<usize> {
+    let Node::Split { first, second, .. } = node else {
+        return None;
+    };
+
+    let sibling = if matches!(**first, Node::Leaf(leaf) if leaf == id) {
+        std::mem::replace(&mut **second, Node::Leaf(usize::MAX))
+    } else if matches!(**second, Node::Leaf(leaf) if leaf == id) {
+        std::mem::replace(&mut **first, Node::Leaf(usize::MAX))
+    } else if contains(first, id) {
+        return remove_leaf(first, id);
+    } else {
+        return remove_leaf(second, id);
+    };
+
+    let next = first_leaf(&sibling);
+    *node = sibling;
+    Some(next)
+}
+
+fn resize_parent(node: &mut Node, id: usize, delta: i16) -> bool {
+    let Node::Split { ratio, first, second, .. } = node else {
+        return false;
+    };
+
+    // resize the innermost split the view is part of
+    let in_first = contains(first, id);
+    let child = if in_first { &mut **first } else { &mut **second };
+    if matches!(child, Node::Split { .. }) && resize_parent(child, id, delta) {
+        return true;
+    }
+
+    let delta = if in_first { delta } else { -delta };
+    *ratio = (*ratio as i16 + delta).clamp(MIN_RATIO as i16, MAX_RATIO as i16) as u16;
+    true
+}
+
+/// Number of views along `direction` in a subtree
+fn span(node: &Node, direction: SplitDirection) -> u16 {
+    match node {
+        Node::Leaf(_) => 1,
+        Node::Split { direction: d, first, second, .. } if *d == direction => {
+            span(first, direction) + span(second, direction)
+        }
+        Node::Split { first, second, .. } => span(first, direction).max(span(second, direction)),
+    }
+}
+
+fn equalize(node: &mut Node) {
+    if let Node::Split { direction, ratio, first, second } = node {
+        let a = span(first, *direction);
+        let b = span(second, *direction);
+        *ratio = (a * 100 / (a + b)).clamp(MIN_RATIO, MAX_RATIO);
+        equalize(first);
+        equalize(second);
+    }
+}
+
+fn layout(node: &Node, area: Rect, out: &mut Vec<(usize, Rect)>) {
+    match node {
+        Node::Leaf(id) => out.push((*id, area)),
+        Node::Split { direction, ratio, first, second } => {
+            let direction = match direction {
+                SplitDirection::Horizontal => Direction::Vertical,
+                SplitDirection::Vertical => Direction::Horizontal,
+            };
+            let parts = Layout::default()
+                .direction(direction)
+                .constraints([Constraint::Percentage(*ratio), Constraint::Min(0)])
+                .split(area);
+            layout(first, parts[0], out);
+            layout(second, parts[1], out);
+        }
+    }
+}
+
+fn leaves(node: &Node, out: &mut Vec<usize>) {
+    match node {
+        Node::Leaf(id) => out.push(*id),
+        Node::Split { first, second, .. } => {
+            leaves(first, out);
+            leaves(second, out);
+        }
+    }
+}
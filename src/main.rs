@@ -1,81 +1,266 @@
 mod utils;
 mod editor;
+mod ui;
+mod cli;
 
 use crossterm::{
-    event::{self, KeyCode, KeyEvent, KeyModifiers},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, KeyCode, KeyEvent, KeyModifiers,
+        MouseButton, MouseEvent, MouseEventKind
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
     backend::{Backend, CrosstermBackend},
     layout::{Constraint, Direction, Layout},
-    style::{Color, Style},
-    text::{Text, Line},
+    style::Style,
+    text::{Line, Text},
     widgets::{Block, Borders, Paragraph},
     Terminal,
 };
 use std::{
-    env, io::{self, stdout},
-    panic
+    env, fs::OpenOptions,
+    io::{self, IsTerminal, Write},
+    panic::{self, AssertUnwindSafe},
+    process,
+    sync::Mutex,
+    thread,
+    time::Duration
 };
 
-use utils::{files::{open_file, save_file}, utils::dbg};
+use utils::{config::{self, Config}, diff::{diff_lines, DiffLine, DiffView}, files::{open_file, read_stdin, write_stdout}, swap, utils::dbg, watcher};
 
 use editor::{
+    commands::Command,
+    cursor::Cursor,
     editor::Editor,
+    window::SplitDirection,
+    keymap::{self, Keymap},
+    indent,
+    large,
     input::{
-        handle_ctrl, handle_command, handle_ctrl_shift
+        handle_ctrl, handle_command, handle_ctrl_shift, handle_alt, keeps_selection, prompt_recovery, prompt_reload, quit_pending, request_quit
     }
 };
+use ui::tabs::TabRegion;
 
 fn main() -> io::Result<()> {
-    // Set up terminal
-    enable_raw_mode()?;
-    let mut stdout = stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    let options = match cli::parse(env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("pte: {}\nTry 'pte --help' for more information.", err);
+            process::exit(2);
+        }
+    };
+    if options.help {
+        print!("{}", cli::usage());
+        return Ok(());
+    }
+    if options.version {
+        println!("pte {}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
 
     //define editor state
     let mut editor = Editor::new();
+    editor.readonly_mode = options.readonly;
+    editor.write_stdout = options.stdout;
 
-    //get filename and populate the editor
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 1 {
-        editor.filename = args[1].clone();
-        open_file(&mut editor);
+    let (explicit, problems) = configure(&mut editor, &options);
+    if options.check_config {
+        if problems.is_empty() {
+            println!("Configuration OK");
+            process::exit(0);
+        }
+        for problem in &problems {
+            eprintln!("{}", problem);
+        }
+        process::exit(1);
+    }
+    // files named on the command line have to work, the user config may be fixed later
+    if !explicit.is_empty() {
+        for problem in &explicit {
+            eprintln!("pte: {}", problem);
+        }
+        process::exit(2);
+    }
+
+    open_files(&mut editor, &options);
+    if let Some(problem) = problems.first() {
+        editor.notif_text = format!("Config error: {} (see pte --check-config)", problem);
     }
 
-    panic::set_hook(Box::new(|_| {
-        dbg("Main thread panicked");
-        println!("An error occurred.");
+    // Set up terminal, stdin and stdout may be pipes so keys are read from
+    // the terminal itself (crossterm does that) and the editor is drawn there
+    enable_raw_mode()?;
+    let mut output = terminal_output()?;
+    execute!(output, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(output);
+    let mut terminal = Terminal::new(backend)?;
+
+    // the terminal is put back where the panic is caught below, panics that
+    // are caught elsewhere or happen on worker threads leave it alone
+    panic::set_hook(Box::new(|info| {
+        let thread = thread::current();
+        dbg(&format!("Thread {} panicked: {}", thread.name().unwrap_or("<unnamed>"), info));
+        if thread.name() == Some("main") {
+            *PANIC_MESSAGE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(info.to_string());
+        }
     }));
 
-    let res = run_app(&mut terminal, &mut editor);
-    // let res = Ok(());
+    let res = match panic::catch_unwind(AssertUnwindSafe(|| run_app(&mut terminal, &mut editor))) {
+        Ok(res) => res,
+        Err(_) => {
+            // leave raw mode first, so the message (and the shell after it) is readable
+            let _ = disable_raw_mode();
+            let _ = execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture);
+            let _ = terminal.show_cursor();
+            if let Some(message) = PANIC_MESSAGE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take() {
+                eprintln!("An error occurred: {}", message);
+            }
+
+            // keep the swap files around, they are what gets recovered
+            for path in swap::dump_unsaved(&mut editor) {
+                eprintln!("Unsaved changes written to {}", path.display());
+            }
+            process::exit(1);
+        }
+    };
+    editor.swap.shutdown();
 
     // Restore terminal
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture)?;
     terminal.show_cursor()?;
 
+    if res.is_ok() && editor.write_stdout {
+        if let Err(err) = write_stdout(&editor) {
+            eprintln!("Can't write to stdout: {}", err);
+            process::exit(1);
+        }
+    }
 
     res
 }
 
+/**
+ * Read the config files and keymap and apply the theme
+ *
+ * Returns the problems with files given on the command line, then all
+ * problems including those in the other config files.
+ */
+fn configure(editor: &mut Editor, options: &cli::Options) -> (Vec<String>, Vec<String>) {
+    let mut explicit = options.config.as_deref().map(config::check).unwrap_or_default();
+
+    editor.config = Config::new(options.config.clone());
+    editor.config.load();
+    // the theme name was checked while parsing
+    if let Some(theme) = &options.theme {
+        editor.config.set("theme", toml::Value::String(theme.clone()));
+    }
+    // for unnamed buffers, this reads the project config of the working directory
+    editor.apply_settings();
+    let mut problems = editor.config.take_errors();
+
+    // without --keymap the user's keymap is used, if they have one
+    let keymap = match &options.keymap {
+        Some(path) => Some((path.clone(), true)),
+        None => keymap::user_keymap_path().filter(|path| path.exists()).map(|path| (path, false)),
+    };
+    if let Some((path, given)) = keymap {
+        match Keymap::load(&path) {
+            Ok(keymap) => editor.keymap = keymap,
+            Err(errors) => {
+                if given {
+                    explicit.extend(errors.iter().cloned());
+                }
+                problems.extend(errors);
+            }
+        }
+    }
+
+    (explicit, problems)
+}
+
+/**
+ * Open every file from the command line in its own buffer, jumping to the
+ * position given with it. `--diff` opens its two files side by side instead.
+ */
+fn open_files(editor: &mut Editor, options: &cli::Options) {
+    if let Some((a, b)) = &options.diff {
+        editor.filename = a.clone();
+        open_file(editor);
+        let old = editor.lines.clone();
+        editor.new_buffer();
+        editor.filename = b.clone();
+        open_file(editor);
+
+        editor.switch_buffer(0);
+        editor.split_window(SplitDirection::Vertical);
+        editor.switch_buffer(1);
+
+        let diff = diff_lines(&old, &editor.lines);
+        let changed = diff.iter().filter(|line| !matches!(line, DiffLine::Same(_))).count();
+        editor.diff_view = Some(DiffView { title: format!("{} vs {}", a, b), diff, scroll: 0 });
+        editor.notif_text = format!("{} changed lines", changed);
+        editor.command_mode = true;
+        editor.command = Command::DiffView;
+        return;
+    }
+
+    for (i, file) in options.files.iter().enumerate() {
+        if i > 0 {
+            editor.new_buffer();
+        }
+        if file.path == "-" {
+            read_stdin(editor);
+        } else {
+            editor.filename = file.path.clone();
+            open_file(editor);
+        }
+        if let Some(line) = file.line {
+            editor.goto(line, file.col.unwrap_or(0));
+        }
+    }
+    editor.switch_buffer(0);
+}
+
+/// What the main thread panicked with, printed once the terminal is restored
+static PANIC_MESSAGE: Mutex<Option<String>> = Mutex::new(None);
+
+/// The terminal to draw on, which isn't stdout when that is redirected
+fn terminal_output() -> io::Result<Box<dyn Write>> {
+    if io::stdout().is_terminal() {
+        return Ok(Box::new(io::stdout()));
+    }
+    #[cfg(unix)]
+    let tty = "/dev/tty";
+    #[cfg(windows)]
+    let tty = "CONOUT$";
+    Ok(Box::new(OpenOptions::new().write(true).open(tty)?))
+}
+
 fn run_app<B: Backend>(terminal: &mut Terminal<B>, editor: &mut Editor) -> io::Result<()> {
+    let mut tab_regions = Vec::new();
     loop {
+        if editor.command == Command::FilePicker {
+            editor.picker.poll(&editor.recent_files);
+        }
+        if !editor.command_mode {
+            watcher::check(editor);
+            prompt_recovery(editor);
+        }
+        if !editor.command_mode {
+            prompt_reload(editor);
+        }
+        swap::update(editor);
+        large::update(editor);
+        config::update(editor);
+
         terminal.draw(|frame| {
             let size = frame.area();
-            let columns = size.height as usize - 3;
-            let middle = columns / 2;
-            let cursor_line = editor.cursors[0].line as usize;
-            let start = if cursor_line > middle {
-                cursor_line - middle
-            } else {
-                0
-            };
-    
+
             // outer layout to add the header and the editor
             let outer_layout = Layout::default()
                 .direction(Direction::Vertical)  // Vertical split
@@ -92,11 +277,18 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, editor: &mut Editor) -> io::R
             let header_block = Block::default()
                 .borders(Borders::ALL);
 
-            let title_left_as_text = Text::from(String::from("Peter's Editor: ") + &editor.filename.clone());
-            let header_left = Paragraph::new(title_left_as_text)
-                .block(header_block.clone())
-                .style(Style::default().fg(Color::Cyan))
-                .alignment(ratatui::layout::Alignment::Center);
+            // the left header holds a tab for every open buffer
+            let header_left = header_block.clone()
+                .title(format!(" {} ", editor.settings.title))
+                .title_bottom(Line::from(format!(
+                    " {}{}{} {} ",
+                    if editor.read_only { "[RO] " } else { "" },
+                    editor.large.as_ref().map_or(String::new(), |large| format!("[{}] ", large.status(editor.lines.len()))),
+                    editor.format.describe(),
+                    indent::describe(&editor.settings),
+                )).right_aligned())
+                .style(Style::default().fg(editor.settings.theme.accent));
+            let tabs_area = header_left.inner(header_layout[0]);
 
             let title_right_as_text = Text::from(editor.notif_text.clone());
             let header_right = Paragraph::new(title_right_as_text)
@@ -106,82 +298,131 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, editor: &mut Editor) -> io::R
             frame.render_widget(header_block, outer_layout[0]);
             frame.render_widget(header_left, header_layout[0]);
             frame.render_widget(header_right, header_layout[1]);
+            tab_regions = ui::tabs::render_tabs(frame, tabs_area, &editor.settings.theme, &editor.buffer_list(), editor.active_buffer);
 
 
-            let editor_layout = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([Constraint::Length(6), Constraint::Min(0)])
-                .split(outer_layout[1]);
-
             // ensure cursors are within bounds
             editor.adjust_cursors();
 
-            // add cursor to editor text
-            let mut lines_with_cursor = Vec::new();
-            let mut line_numbers = Vec::new();
-            let lines = &editor.lines[start..];
-            
-            // get cursor position
-            let cursor_line = editor.cursors[0].line as usize;
-            let col = editor.cursors[0].col as usize;
-            for (mut index, line) in lines.iter().enumerate() {
-                index += start;
-                line_numbers.push(Line::styled(
-                    format!("{:4}  ", index + 1),
-                    Style::default().fg(Color::Cyan),
-                ));
-
-                if index == cursor_line {
-                    // Insert cursor symbol (`█`) at the correct column
-                    let mut line_with_cursor = line.to_string();
-                    if col < line_with_cursor.len() {
-                        let result = panic::catch_unwind(|| {
-                            let mut line_clone = line_with_cursor.clone();
-                            line_clone.insert(col, '█');
-                            line_clone
-                        });
-
-                        match result {
-                            Ok(line) => {
-                                line_with_cursor = line;
-                            }
-                            Err(_) => {
-                                line_with_cursor.push('█');
-                            }
-                        }
-                    } else {
-                        line_with_cursor.push('█'); // cursor at the end of the line
-                    }
-                    lines_with_cursor.push(Line::raw(line_with_cursor));
+            // draw every view, the focused one uses the editor's live state
+            let split = editor.windows.count() > 1;
+            let mut editor_area = outer_layout[1];
+            if editor.explorer.visible {
+                let sidebar_layout = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Length(30), Constraint::Min(0)])
+                    .split(editor_area);
+                let focused = editor.command_mode && matches!(editor.command, Command::Explorer | Command::ExplorerPrompt);
+                ui::explorer::render_explorer(frame, sidebar_layout[0], &editor.settings.theme, &mut editor.explorer, focused, &editor.filename);
+                editor_area = sidebar_layout[1];
+            }
+
+            let rects = editor.windows.layout(editor_area);
+            for (id, area) in &rects {
+                let rows = ui::view::text_rows(*area, split);
+
+                if *id == editor.windows.focused {
+                    let cursor_line = editor.cursors[0].line as usize;
+                    editor.scroll = ui::view::scroll_to_cursor(editor.scroll, cursor_line, rows);
+
+                    let status = split.then(|| (view_label(&editor.filename, editor.changes_saved), true));
+                    let cursor = (cursor_line, editor.cursors[0].col as usize);
+                    let selection = editor.cursors[0].selection()
+                        .map(|(start, end)| ((start.0 as usize, start.1 as usize), (end.0 as usize, end.1 as usize)));
+                    let brackets = editor.matching_bracket();
+                    let first_line = editor.large.as_ref().map_or(0, |large| large.window_start);
+                    ui::view::render_view(frame, *area, &editor.settings.theme, &editor.lines, first_line, editor.settings.tab_width, editor.settings.max_line_length, Some(cursor), selection, brackets, editor.scroll, status);
+                    continue;
+                }
+
+                let view = editor.windows.views.get_mut(id).expect("laid out view exists");
+                let (lines, filename, saved, large, settings) = if view.buffer == editor.active_buffer {
+                    (&editor.lines, &editor.filename, editor.changes_saved, &editor.large, &editor.settings)
                 } else {
-                    lines_with_cursor.push(Line::raw(line.clone()));
+                    let buffer = &editor.buffers[view.buffer];
+                    (&buffer.lines, &buffer.filename, buffer.changes_saved, &buffer.large, &buffer.settings)
+                };
+                let first_line = large.as_ref().map_or(0, |large| large.window_start);
+
+                // the buffer may have been edited from another view
+                let cursor_line = (view.cursors[0].line as usize).min(lines.len().saturating_sub(1));
+                view.scroll = ui::view::scroll_to_cursor(view.scroll, cursor_line, rows);
+                ui::view::render_view(frame, *area, &editor.settings.theme, lines, first_line, settings.tab_width, settings.max_line_length, None, None, None, view.scroll, Some((view_label(filename, saved), false)));
+            }
+            editor.windows.rects = rects;
+
+            if editor.command_mode && editor.command == Command::FilePicker {
+                ui::picker::render_picker(frame, &editor.settings.theme, &mut editor.picker);
+            }
+            if editor.command_mode && editor.command == Command::BufferList {
+                ui::buffers::render_buffer_list(frame, &editor.settings.theme, &editor.buffer_list(), editor.buffer_selection, editor.active_buffer);
+            }
+            if editor.command_mode && editor.command == Command::RecoverPrompt {
+                if let Some(recovery) = editor.recoveries.first_mut() {
+                    if let Some(diff) = &recovery.diff {
+                        let title = format!("{} (on disk) vs swap file", recovery.filename);
+                        ui::diff::render_diff(frame, &editor.settings.theme, &title, diff, &mut recovery.diff_scroll);
+                    }
+                }
+            }
+            if editor.command_mode && editor.command == Command::ReloadPrompt {
+                if let Some(change) = editor.external_changes.first_mut() {
+                    if let Some(diff) = &change.diff {
+                        let title = format!("{} in the editor vs on disk", change.filename);
+                        ui::diff::render_diff(frame, &editor.settings.theme, &title, diff, &mut change.diff_scroll);
+                    }
+                }
+            }
+            if editor.command_mode && editor.command == Command::DiffView {
+                if let Some(view) = editor.diff_view.as_mut() {
+                    ui::diff::render_diff(frame, &editor.settings.theme, &view.title, &view.diff, &mut view.scroll);
                 }
             }
+            if editor.command_mode && editor.command == Command::QuitPrompt {
+                let list = editor.buffer_list();
+                let names: Vec<String> = quit_pending(editor).iter().map(|i| list[*i].0.clone()).collect();
+                ui::quit::render_quit_prompt(frame, &editor.settings.theme, &names, editor.quit_selection);
+            }
+            if editor.command_mode && editor.command == Command::OpenFile {
+                ui::completion::render_completion(frame, &editor.settings.theme, &editor.completer, header_layout[1]);
+            }
+        })?;
 
-            // create text and lines for the editor
-            let editor_text = Text::from(lines_with_cursor);
-            let editor_paragraph = Paragraph::new(editor_text)
-                .block(Block::default())
-                .alignment(ratatui::layout::Alignment::Left);
+        // poll so background work (like file indexing) can redraw without a key press
+        if !event::poll(Duration::from_millis(100))? {
+            continue;
+        }
 
-            let lines_text = Text::from(line_numbers);
-            let lines_paragraph = Paragraph::new(lines_text)
-                .block(Block::default())
-                .alignment(ratatui::layout::Alignment::Right);
+        let event = event::read()?;
 
-            // Render the blocks in the nested layout
-            frame.render_widget(lines_paragraph, editor_layout[0]);
-            frame.render_widget(editor_paragraph, editor_layout[1]);
-        })?;
+        // clicking a tab switches to that buffer
+        if let event::Event::Mouse(MouseEvent { kind: MouseEventKind::Down(MouseButton::Left), column, row, .. }) = event {
+            if !editor.command_mode {
+                if let Some(tab) = tab_regions.iter().find(|t: &&TabRegion| t.y == row && column >= t.x && column < t.x + t.width) {
+                    editor.switch_buffer(tab.index);
+                }
+            }
+            continue;
+        }
 
-        if let event::Event::Key(KeyEvent { code, modifiers, .. }) = event::read()? {
+        if let event::Event::Key(KeyEvent { code, modifiers, .. }) = event {
+            let (code, modifiers) = editor.keymap.apply(code, modifiers);
             if editor.command_mode {
                 handle_command(editor, code, modifiers);
+                if editor.quit {
+                    break;
+                }
+                continue;
+            }
+
+            let plain = !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+            let edits = matches!(code, KeyCode::Tab | KeyCode::BackTab | KeyCode::Backspace | KeyCode::Enter | KeyCode::Char(_));
+            if plain && edits && !editor.editable() {
                 continue;
             }
 
             match (code, modifiers) {
-                (_, KeyModifiers::CONTROL) => {
+                (_, _) if modifiers.contains(KeyModifiers::CONTROL) => {
                     if modifiers.contains(KeyModifiers::SHIFT) {
                         handle_ctrl_shift(editor, code, modifiers);
                     }
@@ -189,10 +430,20 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, editor: &mut Editor) -> io::R
                         handle_ctrl(editor, code, modifiers);
                     }
                 }
+                (_, _) if modifiers.contains(KeyModifiers::ALT) => {
+                    handle_alt(editor, code, modifiers);
+                }
+                // TAB indents the lines of a selection, SHIFT + TAB outdents lines always
+                (KeyCode::Tab, _) if editor.cursors.iter().any(|cursor| cursor.selection().is_some()) => {
+                    editor.indent_lines();
+                }
                 (KeyCode::Tab, _) => {
                     editor.changes_saved = false;
                     editor.tab();
                 }
+                (KeyCode::BackTab, _) => {
+                    editor.outdent_lines();
+                }
                 (KeyCode::Backspace, _) => {
                     editor.changes_saved = false;
                     if modifiers.contains(KeyModifiers::SHIFT) {
@@ -203,10 +454,11 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, editor: &mut Editor) -> io::R
                     }
                 }
                 (KeyCode::Enter, _) => {
+                    editor.changes_saved = false;
                     editor.new_line();
                 }
                 (KeyCode::Esc, _) => {
-                    break;
+                    request_quit(editor);
                 }
                 (KeyCode::Char(' '), _) => {
                     editor.changes_saved = false;
@@ -244,12 +496,32 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, editor: &mut Editor) -> io::R
                         editor.down();
                     }
                 }
-                _ => {
+                (KeyCode::Char(c), _) => {
                     editor.changes_saved = false;
-                    editor.insert(code.to_string().chars().next().unwrap());
+                    editor.insert(c);
                 }
+                _ => {}
+            }
+
+            // a selection lasts while it's being extended
+            if !keeps_selection(code, modifiers) {
+                editor.cursors.iter_mut().for_each(Cursor::clear_selection);
+            }
+
+            if editor.quit {
+                break;
             }
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Status line text for a split view
+fn view_label(filename: &str, saved: bool) -> String {
+    let name = if filename.is_empty() { "[No Name]" } else { filename };
+    if saved {
+        name.to_string()
+    } else {
+        format!("{} [+]", name)
+    }
+}
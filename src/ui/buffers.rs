@@ -0,0 +1,41 @@
+use ratatui::{
+    text::{Line, Text},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use super::{centered_rect, theme::Theme};
+
+/// Draw the buffer switcher as a popup over the editor
+///
+/// `buffers` is the (filename, changes_saved) of every open buffer
+pub fn render_buffer_list(frame: &mut Frame, theme: &Theme, buffers: &[(String, bool)], selected: usize, active: usize) {
+    let area = centered_rect(60, 50, frame.area());
+    frame.render_widget(Clear, area);
+
+    let height = area.height.saturating_sub(2) as usize;
+    let start = (selected + 1).saturating_sub(height);
+    let lines: Vec<Line> = buffers.iter()
+        .enumerate()
+        .skip(start)
+        .take(height)
+        .map(|(i, (name, saved))| {
+            let marker = if i == active { "%" } else { " " };
+            let dirty = if *saved { " " } else { "+" };
+            let text = format!("{:3} {}{} {}", i + 1, marker, dirty, name);
+            if i == selected {
+                Line::styled(text, theme.selected())
+            } else {
+                Line::raw(text)
+            }
+        })
+        .collect();
+
+    let list = Paragraph::new(Text::from(lines)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Buffers ")
+            .title_bottom(" enter: open  d: close  esc: cancel "),
+    );
+    frame.render_widget(list, area);
+}
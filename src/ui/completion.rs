@@ -0,0 +1,57 @@
+use ratatui::{
+    layout::Rect,
+    style::Style,
+    text::{Line, Text},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use crate::editor::completion::PathCompleter;
+use super::theme::Theme;
+
+const MAX_ROWS: usize = 10;
+
+/// Draw the completion candidates in a small popup under the prompt
+///
+/// `anchor` is the area of the prompt, the popup is drawn right below it
+pub fn render_completion(frame: &mut Frame, theme: &Theme, completer: &PathCompleter, anchor: Rect) {
+    if !completer.is_active() {
+        return;
+    }
+
+    let mut lines: Vec<Line> = Vec::new();
+    if completer.candidates.is_empty() {
+        lines.push(Line::styled("No matches", Style::default().fg(theme.muted)));
+    }
+
+    // keep the selected candidate on screen
+    let selected = completer.selected.unwrap_or(0);
+    let start = (selected + 1).saturating_sub(MAX_ROWS);
+    for (i, candidate) in completer.candidates.iter().enumerate().skip(start).take(MAX_ROWS) {
+        if completer.selected == Some(i) {
+            lines.push(Line::styled(
+                candidate.clone(),
+                theme.selected(),
+            ));
+        } else {
+            lines.push(Line::raw(candidate.clone()));
+        }
+    }
+
+    let screen = frame.area();
+    let top = anchor.y + anchor.height;
+    let height = (lines.len() as u16 + 2).min(screen.height.saturating_sub(top));
+    let area = Rect::new(anchor.x, top, anchor.width, height);
+
+    let title = if completer.candidates.len() > MAX_ROWS {
+        format!(" {} candidates ", completer.candidates.len())
+    } else {
+        String::new()
+    };
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(Text::from(lines)).block(Block::default().borders(Borders::ALL).title(title)),
+        area,
+    );
+}
@@ -0,0 +1,76 @@
+use ratatui::{
+    style::Style,
+    text::{Line, Text},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use crate::utils::diff::DiffLine;
+use super::{centered_rect, theme::Theme};
+
+/// Unchanged lines shown around every change
+const CONTEXT: usize = 2;
+
+/// Draw a line diff as a popup over the editor, long unchanged runs are folded
+///
+/// `scroll` is clamped to the diff, so callers can keep incrementing it
+pub fn render_diff(frame: &mut Frame, theme: &Theme, title: &str, diff: &[DiffLine], scroll: &mut usize) {
+    let area = centered_rect(80, 70, frame.area());
+    frame.render_widget(Clear, area);
+
+    let rows = fold(diff, theme);
+    let height = area.height.saturating_sub(2) as usize;
+    *scroll = (*scroll).min(rows.len().saturating_sub(height));
+
+    let lines: Vec<Line> = rows.into_iter().skip(*scroll).take(height).collect();
+    let text = if lines.is_empty() {
+        Text::from("No differences")
+    } else {
+        Text::from(lines)
+    };
+
+    let paragraph = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" {} ", title))
+            .title_bottom(" ↑/↓: scroll  d: hide diff "),
+    );
+    frame.render_widget(paragraph, area);
+}
+
+fn fold(diff: &[DiffLine], theme: &Theme) -> Vec<Line<'static>> {
+    // mark the unchanged lines close enough to a change to be shown
+    let mut shown = vec![false; diff.len()];
+    for (i, line) in diff.iter().enumerate() {
+        if !matches!(line, DiffLine::Same(_)) {
+            let end = (i + CONTEXT + 1).min(diff.len());
+            shown[i.saturating_sub(CONTEXT)..end].fill(true);
+        }
+    }
+
+    let mut rows = Vec::new();
+    let mut folded = false;
+    for (i, line) in diff.iter().enumerate() {
+        match line {
+            DiffLine::Same(text) if shown[i] => {
+                rows.push(Line::raw(format!("  {}", text)));
+                folded = false;
+            }
+            DiffLine::Same(_) => {
+                if !folded {
+                    rows.push(Line::styled("  ...", Style::default().fg(theme.muted)));
+                    folded = true;
+                }
+            }
+            DiffLine::Removed(text) => {
+                rows.push(Line::styled(format!("- {}", text), Style::default().fg(theme.removed)));
+                folded = false;
+            }
+            DiffLine::Added(text) => {
+                rows.push(Line::styled(format!("+ {}", text), Style::default().fg(theme.added)));
+                folded = false;
+            }
+        }
+    }
+    rows
+}
@@ -0,0 +1,63 @@
+use ratatui::{
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Line, Text},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+use std::path::Path;
+
+use crate::editor::explorer::{normalize, Explorer};
+use super::theme::Theme;
+
+/// Draw the file explorer sidebar
+///
+/// `current_file` is highlighted so it is easy to see where you are
+pub fn render_explorer(frame: &mut Frame, area: Rect, theme: &Theme, explorer: &mut Explorer, focused: bool, current_file: &str) {
+    let block = Block::default()
+        .borders(Borders::RIGHT)
+        .border_style(Style::default().fg(if focused { theme.accent } else { theme.muted }));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let rows = inner.height as usize;
+    if explorer.selected < explorer.scroll {
+        explorer.scroll = explorer.selected;
+    } else if rows > 0 && explorer.selected >= explorer.scroll + rows {
+        explorer.scroll = explorer.selected + 1 - rows;
+    }
+
+    let current = normalize(Path::new(current_file));
+    let lines: Vec<Line> = explorer.entries.iter()
+        .enumerate()
+        .skip(explorer.scroll)
+        .take(rows)
+        .map(|(i, entry)| {
+            let icon = match (entry.is_dir, explorer.is_expanded(&entry.path)) {
+                (true, true) => "▾ ",
+                (true, false) => "▸ ",
+                (false, _) => "  ",
+            };
+            let text = format!("{}{}{}", "  ".repeat(entry.depth), icon, entry.name);
+
+            let mut style = if entry.is_dir {
+                Style::default().fg(theme.accent)
+            } else {
+                Style::default()
+            };
+            if entry.path == current {
+                style = style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+            }
+            if i == explorer.selected {
+                style = if focused {
+                    style.fg(theme.on_accent).bg(theme.accent)
+                } else {
+                    style.bg(theme.muted)
+                };
+            }
+            Line::styled(text, style)
+        })
+        .collect();
+
+    frame.render_widget(Paragraph::new(Text::from(lines)), inner);
+}
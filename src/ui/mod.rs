@@ -0,0 +1,32 @@
+pub mod picker;
+pub mod completion;
+pub mod buffers;
+pub mod tabs;
+pub mod view;
+pub mod explorer;
+pub mod diff;
+pub mod quit;
+pub mod theme;
+
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+
+/// Get a rect centered in `area`, sized as a percentage of it
+pub fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(area);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
@@ -0,0 +1,64 @@
+use ratatui::{
+    layout::{Constraint, Direction, Layout},
+    style::Style,
+    text::{Line, Text},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use crate::editor::picker::FilePicker;
+use super::{centered_rect, theme::Theme};
+
+/// Draw the fuzzy file picker as a popup over the editor
+pub fn render_picker(frame: &mut Frame, theme: &Theme, picker: &mut FilePicker) {
+    let area = centered_rect(80, 70, frame.area());
+    frame.render_widget(Clear, area);
+
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)])
+        .split(area);
+
+    let body_layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(layout[1]);
+
+    // query input
+    let status = if picker.indexing {
+        format!(" {}/{} (indexing...) ", picker.matches.len(), picker.files.len())
+    } else {
+        format!(" {}/{} ", picker.matches.len(), picker.files.len())
+    };
+    let input = Paragraph::new(Text::from(format!("> {}█", picker.query)))
+        .block(Block::default().borders(Borders::ALL).title(" Open file ").title_bottom(status))
+        .style(Style::default().fg(theme.accent));
+    frame.render_widget(input, layout[0]);
+
+    // list of matches, scrolled so the selection is visible
+    let height = body_layout[0].height.saturating_sub(2) as usize;
+    let start = (picker.selected + 1).saturating_sub(height);
+    let items: Vec<Line> = picker.matches.iter()
+        .enumerate()
+        .skip(start)
+        .take(height)
+        .map(|(i, file_index)| {
+            let file = picker.files[*file_index].clone();
+            if i == picker.selected {
+                Line::styled(file, theme.selected())
+            } else {
+                Line::raw(file)
+            }
+        })
+        .collect();
+    let list = Paragraph::new(Text::from(items))
+        .block(Block::default().borders(Borders::ALL));
+    frame.render_widget(list, body_layout[0]);
+
+    // preview of the highlighted file
+    let title = picker.selected_file().cloned().unwrap_or_default();
+    let preview: Vec<Line> = picker.preview().into_iter().map(Line::raw).collect();
+    let preview = Paragraph::new(Text::from(preview))
+        .block(Block::default().borders(Borders::ALL).title(format!(" {} ", title)));
+    frame.render_widget(preview, body_layout[1]);
+}
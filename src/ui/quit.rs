@@ -0,0 +1,37 @@
+use ratatui::{
+    text::{Line, Text},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use super::{centered_rect, theme::Theme};
+
+/// Draw the list of buffers with unsaved changes shown before quitting
+pub fn render_quit_prompt(frame: &mut Frame, theme: &Theme, names: &[String], selected: usize) {
+    let area = centered_rect(60, 40, frame.area());
+    frame.render_widget(Clear, area);
+
+    let height = area.height.saturating_sub(2) as usize;
+    let start = (selected + 1).saturating_sub(height);
+    let lines: Vec<Line> = names.iter()
+        .enumerate()
+        .skip(start)
+        .take(height)
+        .map(|(i, name)| {
+            let text = format!(" + {}", name);
+            if i == selected {
+                Line::styled(text, theme.selected())
+            } else {
+                Line::raw(text)
+            }
+        })
+        .collect();
+
+    let list = Paragraph::new(Text::from(lines)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Unsaved changes ")
+            .title_bottom(" s: save  d: discard  S: save all  D: discard all  esc: cancel "),
+    );
+    frame.render_widget(list, area);
+}
@@ -0,0 +1,101 @@
+use ratatui::{
+    layout::Rect,
+    style::Style,
+    text::{Line, Span},
+    widgets::Paragraph,
+    Frame,
+};
+use std::path::Path;
+
+use super::theme::Theme;
+
+const MAX_LABEL_WIDTH: usize = 24;
+
+///
+/// ### Screen area of a tab, for mouse clicks
+///
+pub struct TabRegion {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub index: usize,
+}
+
+/// Draw one tab per open buffer into `area`
+///
+/// `tabs` is the (filename, changes_saved) of every buffer. If the tabs
+/// don't fit, a window of tabs around the active one is shown, with arrows
+/// marking the hidden ones. Returns where each visible tab was drawn.
+pub fn render_tabs(frame: &mut Frame, area: Rect, theme: &Theme, tabs: &[(String, bool)], active: usize) -> Vec<TabRegion> {
+    let labels: Vec<String> = tabs.iter()
+        .enumerate()
+        .map(|(i, (name, saved))| tab_label(i, name, *saved))
+        .collect();
+    let widths: Vec<usize> = labels.iter().map(|l| l.chars().count()).collect();
+
+    // grow a window of visible tabs outwards from the active one
+    let available = area.width as usize;
+    let (mut first, mut last) = (active, active);
+    let mut used = widths[active];
+    loop {
+        let mut grew = false;
+        // leave room for the overflow arrows on either side
+        let reserve = (first > 0) as usize + (last + 1 < tabs.len()) as usize;
+        if last + 1 < tabs.len() && used + widths[last + 1] + reserve <= available {
+            last += 1;
+            used += widths[last];
+            grew = true;
+        }
+        let reserve = (first > 0) as usize + (last + 1 < tabs.len()) as usize;
+        if first > 0 && used + widths[first - 1] + reserve <= available {
+            first -= 1;
+            used += widths[first];
+            grew = true;
+        }
+        if !grew {
+            break;
+        }
+    }
+
+    let mut spans = Vec::new();
+    let mut regions = Vec::new();
+    let mut x = area.x;
+    let arrow_style = Style::default().fg(theme.muted);
+
+    if first > 0 {
+        spans.push(Span::styled("‹", arrow_style));
+        x += 1;
+    }
+    for (i, label) in labels.iter().enumerate().take(last + 1).skip(first) {
+        let style = if i == active {
+            theme.selected()
+        } else {
+            Style::default().fg(theme.accent)
+        };
+        let width = (widths[i] as u16).min(area.x + area.width - x);
+        regions.push(TabRegion { x, y: area.y, width, index: i });
+        spans.push(Span::styled(label.clone(), style));
+        x += width;
+    }
+    if last + 1 < tabs.len() {
+        spans.push(Span::styled("›", arrow_style));
+    }
+
+    frame.render_widget(Paragraph::new(Line::from(spans)), area);
+    regions
+}
+
+fn tab_label(index: usize, name: &str, saved: bool) -> String {
+    let name = Path::new(name)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| name.to_string());
+
+    let mut name: String = name;
+    if name.chars().count() > MAX_LABEL_WIDTH {
+        name = name.chars().take(MAX_LABEL_WIDTH - 1).collect::<String>() + "…";
+    }
+
+    let dirty = if saved { "" } else { " +" };
+    format!(" {}:{}{} ", index + 1, name, dirty)
+}
@@ -0,0 +1,45 @@
+use ratatui::style::{Color, Modifier, Style};
+
+///
+/// ### Colors the interface is drawn with
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Theme {
+    pub name: &'static str,
+    /// Borders, line numbers and the highlighted item of a list
+    pub accent: Color,
+    /// Text drawn on top of `accent`
+    pub on_accent: Color,
+    /// Hints, folds and unfocused elements
+    pub muted: Color,
+    pub added: Color,
+    pub removed: Color,
+}
+
+pub const THEMES: [Theme; 5] = [
+    Theme { name: "default", accent: Color::Cyan, on_accent: Color::Black, muted: Color::DarkGray, added: Color::Green, removed: Color::Red },
+    Theme { name: "ocean", accent: Color::Blue, on_accent: Color::White, muted: Color::DarkGray, added: Color::Cyan, removed: Color::Magenta },
+    Theme { name: "forest", accent: Color::Green, on_accent: Color::Black, muted: Color::DarkGray, added: Color::LightGreen, removed: Color::LightRed },
+    Theme { name: "amber", accent: Color::Yellow, on_accent: Color::Black, muted: Color::DarkGray, added: Color::Green, removed: Color::Red },
+    Theme { name: "mono", accent: Color::White, on_accent: Color::Black, muted: Color::Gray, added: Color::White, removed: Color::Gray },
+];
+
+impl Theme {
+    pub fn new() -> Self {
+        THEMES[0]
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        THEMES.iter().find(|theme| theme.name == name).copied()
+    }
+
+    /// Names of the built-in themes, for error messages
+    pub fn names() -> String {
+        THEMES.iter().map(|theme| theme.name).collect::<Vec<_>>().join(", ")
+    }
+
+    /// The highlighted item of a list, or the active tab
+    pub fn selected(&self) -> Style {
+        Style::default().fg(self.on_accent).bg(self.accent).add_modifier(Modifier::BOLD)
+    }
+}
@@ -0,0 +1,182 @@
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{Block, Paragraph},
+    Frame,
+};
+
+use super::theme::Theme;
+
+/// Get a scroll offset that keeps `cursor_line` inside a window of `rows` lines
+pub fn scroll_to_cursor(scroll: usize, cursor_line: usize, rows: usize) -> usize {
+    let rows = rows.max(1);
+    if cursor_line < scroll {
+        cursor_line
+    } else if cursor_line >= scroll + rows {
+        cursor_line + 1 - rows
+    } else {
+        scroll
+    }
+}
+
+/// Number of text rows a view drawn in `area` has
+pub fn text_rows(area: Rect, has_status: bool) -> usize {
+    area.height.saturating_sub(has_status as u16) as usize
+}
+
+/// Draw a line-number gutter and the visible lines of a buffer into `area`
+///
+/// `cursor` is the (line, col) to draw the block cursor at, if any, and
+/// `selection` the (line, col) range to highlight. `brackets` are the
+/// (line, col) positions of a pair of matching brackets to mark.
+/// Tabs are drawn as spaces up to the next multiple of `tab_width`, and text
+/// past column `max_line_length` is colored, 0 turns that off.
+/// `status` is a (label, focused) pair drawn as a status line at the bottom.
+/// `first_line` is the line number of `lines[0]` minus one, which is only
+/// non-zero for the window of a large file.
+#[allow(clippy::too_many_arguments)]
+pub fn render_view(
+    frame: &mut Frame,
+    area: Rect,
+    theme: &Theme,
+    lines: &[String],
+    first_line: usize,
+    tab_width: usize,
+    max_line_length: usize,
+    cursor: Option<(usize, usize)>,
+    selection: Option<((usize, usize), (usize, usize))>,
+    brackets: Option<((usize, usize), (usize, usize))>,
+    scroll: usize,
+    status: Option<(String, bool)>,
+) {
+    let mut text_area = area;
+    if let Some((label, focused)) = status {
+        text_area.height = text_rows(area, true) as u16;
+        let status_area = Rect::new(area.x, area.y + text_area.height, area.width, 1);
+
+        let style = if focused {
+            theme.selected()
+        } else {
+            Style::default().fg(theme.accent).bg(theme.muted)
+        };
+        let width = status_area.width as usize;
+        frame.render_widget(Paragraph::new(format!(" {:<width$}", label, width = width)).style(style), status_area);
+    }
+
+    // wide enough for the last visible line number, and at least 4 digits
+    let digits = (first_line + scroll + text_area.height as usize).to_string().len().max(4);
+    let editor_layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(digits as u16 + 2), Constraint::Min(0)])
+        .split(text_area);
+
+    // add cursor to editor text
+    let mut lines_with_cursor = Vec::new();
+    let mut line_numbers = Vec::new();
+    let start = scroll.min(lines.len().saturating_sub(1));
+    let visible = &lines[start..lines.len().min(start + text_area.height as usize)];
+
+    for (mut index, line) in visible.iter().enumerate() {
+        index += start;
+        let selected = selection.and_then(|((start_line, start_col), (end_line, end_col))| {
+            let from = if index == start_line { start_col } else { 0 };
+            let to = if index == end_line { end_col } else { usize::MAX };
+            (start_line..=end_line).contains(&index).then_some((from, to))
+        });
+        let marked: Vec<usize> = brackets.iter()
+            .flat_map(|(first, second)| [first, second])
+            .filter(|(line, _)| *line == index)
+            .map(|(_, col)| *col)
+            .collect();
+        line_numbers.push(Line::styled(
+            format!("{:digits$}  ", first_line + index + 1, digits = digits),
+            Style::default().fg(theme.accent),
+        ));
+
+        match cursor {
+            Some((cursor_line, col)) if index == cursor_line => {
+                // Insert cursor symbol (`█`) at the correct column, or before
+                // the char the column is in the middle of
+                let mut col = col.min(line.len());
+                while !line.is_char_boundary(col) {
+                    col -= 1;
+                }
+                let mut line_with_cursor = line.to_string();
+                let cursor_char = line[..col].chars().count();
+                line_with_cursor.insert(col, '█');
+                lines_with_cursor.push(display_line(&line_with_cursor, tab_width, max_line_length, Some(cursor_char), selected, &marked, theme));
+            }
+            _ => {
+                lines_with_cursor.push(display_line(line, tab_width, max_line_length, None, selected, &marked, theme));
+            }
+        }
+    }
+
+    // create text and lines for the editor
+    let editor_text = Text::from(lines_with_cursor);
+    let editor_paragraph = Paragraph::new(editor_text)
+        .block(Block::default())
+        .alignment(ratatui::layout::Alignment::Left);
+
+    let lines_text = Text::from(line_numbers);
+    let lines_paragraph = Paragraph::new(lines_text)
+        .block(Block::default())
+        .alignment(ratatui::layout::Alignment::Right);
+
+    // Render the blocks in the nested layout
+    frame.render_widget(lines_paragraph, editor_layout[0]);
+    frame.render_widget(editor_paragraph, editor_layout[1]);
+}
+
+/**
+ * Expand the tabs of `text`, color what goes past column `limit`,
+ * highlight the `selected` byte range and mark the chars at `marked` bytes
+ *
+ * `cursor` is the char index of the drawn cursor, which doesn't take up a
+ * column of the text or a byte of the selection.
+ */
+fn display_line(text: &str, tab_width: usize, limit: usize, cursor: Option<usize>, selected: Option<(usize, usize)>, marked: &[usize], theme: &Theme) -> Line<'static> {
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    let mut shown = String::new();
+    let mut style = Style::default();
+    let mut column = 0;
+    let mut byte = 0;
+    for (i, c) in text.chars().enumerate() {
+        let is_cursor = cursor == Some(i);
+        let mut next = Style::default();
+        if limit > 0 && column >= limit && !is_cursor {
+            next = next.fg(theme.removed);
+        }
+        if !is_cursor && selected.is_some_and(|(from, to)| (from..to).contains(&byte)) {
+            next = next.add_modifier(Modifier::REVERSED);
+        }
+        if !is_cursor && marked.contains(&byte) {
+            next = next.fg(theme.accent).add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+        }
+        if next != style && !shown.is_empty() {
+            spans.push(Span::styled(std::mem::take(&mut shown), style));
+        }
+        style = next;
+
+        match c {
+            _ if is_cursor => shown.push(c),
+            '\t' => {
+                let stop = (column / tab_width + 1) * tab_width;
+                shown.push_str(&" ".repeat(stop - column));
+                column = stop;
+            }
+            c => {
+                shown.push(c);
+                column += 1;
+            }
+        }
+        if !is_cursor {
+            byte += c.len_utf8();
+        }
+    }
+    if !shown.is_empty() {
+        spans.push(Span::styled(shown, style));
+    }
+    Line::from(spans)
+}
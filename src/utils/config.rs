@@ -0,0 +1,486 @@
+use crate::{editor::{indent::Indent, language, large}, ui::theme::Theme, Editor};
+use super::{editorconfig, format::LineEnding};
+use encoding_rs::Encoding;
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime}
+};
+use toml::{Table, Value};
+
+/// How often config files are checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Per-project config file, looked for next to a file and in every directory above it
+pub const PROJECT_FILE: &str = ".pte.toml";
+
+#[cfg(unix)]
+const GLOBAL_FILE: &str = "/etc/pte/config.toml";
+
+#[derive(Clone, Copy)]
+enum Kind {
+    /// A whole number in an inclusive range
+    Int(i64, i64),
+    Bool,
+    Str,
+    Theme,
+}
+
+/// Every option config files and `:set` know, with the values it takes
+const OPTIONS: [(&str, Kind); 12] = [
+    ("tab_width", Kind::Int(1, 16)),
+    ("hard_tabs", Kind::Bool),
+    ("detect_indent", Kind::Bool),
+    ("history_size", Kind::Int(1, 10_000)),
+    ("large_file_mb", Kind::Int(1, 1 << 20)),
+    ("auto_indent", Kind::Bool),
+    ("indent_after", Kind::Str),
+    ("auto_pairs", Kind::Bool),
+    ("trim_trailing_whitespace", Kind::Bool),
+    ("max_line_length", Kind::Int(0, 10_000)),
+    ("title", Kind::Str),
+    ("theme", Kind::Theme),
+];
+
+/// Short names for options, mostly the ones vim users will reach for
+const ALIASES: [(&str, &str); 12] = [
+    ("tabwidth", "tab_width"),
+    ("ts", "tab_width"),
+    ("hardtabs", "hard_tabs"),
+    ("detectindent", "detect_indent"),
+    ("historysize", "history_size"),
+    ("largefile", "large_file_mb"),
+    ("autoindent", "auto_indent"),
+    ("ai", "auto_indent"),
+    ("indentafter", "indent_after"),
+    ("autopairs", "auto_pairs"),
+    ("trimwhitespace", "trim_trailing_whitespace"),
+    ("maxlinelength", "max_line_length"),
+];
+
+///
+/// ### Option values in effect for a buffer
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub tab_width: usize,
+    /// Indent with tab characters instead of spaces
+    pub hard_tabs: bool,
+    /// Guess `tab_width` and `hard_tabs` from a file's contents when it's opened
+    pub detect_indent: bool,
+    /// Undo steps kept per buffer
+    pub history_size: usize,
+    /// Files at least this big are opened in large file mode
+    pub large_file_mb: u64,
+    /// Keep the indentation of the current line on enter
+    pub auto_indent: bool,
+    /// After a line ending in one of these, the next line is indented one level more
+    pub indent_after: String,
+    /// Type the closing bracket or quote along with the opening one
+    pub auto_pairs: bool,
+    /// Remove whitespace at the end of lines when saving
+    pub trim_trailing_whitespace: bool,
+    /// Text past this column is highlighted, 0 turns it off
+    pub max_line_length: usize,
+    /// Line ending files are saved with, from `.editorconfig`
+    pub end_of_line: Option<LineEnding>,
+    /// Encoding files are read and saved in and whether they have a BOM, from `.editorconfig`
+    pub charset: Option<(&'static Encoding, bool)>,
+    /// Whether files end with a newline, from `.editorconfig`
+    pub final_newline: Option<bool>,
+    /// Shown at the top of the header
+    pub title: String,
+    pub theme: Theme,
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Self {
+            tab_width: 4,
+            hard_tabs: false,
+            detect_indent: true,
+            history_size: 100,
+            large_file_mb: large::DEFAULT_THRESHOLD_MB,
+            auto_indent: true,
+            indent_after: String::from("{"),
+            auto_pairs: true,
+            trim_trailing_whitespace: false,
+            max_line_length: 0,
+            end_of_line: None,
+            charset: None,
+            final_newline: None,
+            title: String::from("Peter's Editor"),
+            theme: Theme::new(),
+        }
+    }
+
+    /// The text one level of indentation is made of
+    pub fn indent_unit(&self) -> String {
+        match self.hard_tabs {
+            true => String::from("\t"),
+            false => " ".repeat(self.tab_width),
+        }
+    }
+
+    pub fn large_file_threshold(&self) -> u64 {
+        self.large_file_mb * 1024 * 1024
+    }
+
+    /// Apply options that were validated when they were read
+    fn apply(&mut self, options: &Table) {
+        for (name, value) in options {
+            match (name.as_str(), value) {
+                ("tab_width", Value::Integer(n)) => self.tab_width = *n as usize,
+                ("hard_tabs", Value::Boolean(b)) => self.hard_tabs = *b,
+                ("detect_indent", Value::Boolean(b)) => self.detect_indent = *b,
+                ("history_size", Value::Integer(n)) => self.history_size = *n as usize,
+                ("large_file_mb", Value::Integer(n)) => self.large_file_mb = *n as u64,
+                ("auto_indent", Value::Boolean(b)) => self.auto_indent = *b,
+                ("indent_after", Value::String(s)) => self.indent_after = s.clone(),
+                ("auto_pairs", Value::Boolean(b)) => self.auto_pairs = *b,
+                ("trim_trailing_whitespace", Value::Boolean(b)) => self.trim_trailing_whitespace = *b,
+                ("max_line_length", Value::Integer(n)) => self.max_line_length = *n as usize,
+                ("title", Value::String(s)) => self.title = s.clone(),
+                ("theme", Value::String(s)) => self.theme = Theme::by_name(s).unwrap_or(self.theme),
+                _ => {}
+            }
+        }
+    }
+
+    /// Value of an option as `:set` reports it
+    pub fn get(&self, name: &str) -> String {
+        match name {
+            "tab_width" => self.tab_width.to_string(),
+            "hard_tabs" => self.hard_tabs.to_string(),
+            "detect_indent" => self.detect_indent.to_string(),
+            "history_size" => self.history_size.to_string(),
+            "large_file_mb" => self.large_file_mb.to_string(),
+            "auto_indent" => self.auto_indent.to_string(),
+            "indent_after" => format!("{:?}", self.indent_after),
+            "auto_pairs" => self.auto_pairs.to_string(),
+            "trim_trailing_whitespace" => self.trim_trailing_whitespace.to_string(),
+            "max_line_length" => self.max_line_length.to_string(),
+            "title" => format!("{:?}", self.title),
+            "theme" => self.theme.name.to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// The option called `name` (or one of its aliases)
+fn option(name: &str) -> Option<(&'static str, Kind)> {
+    let name = ALIASES.iter().find(|(alias, _)| *alias == name).map_or(name, |(_, option)| option);
+    OPTIONS.iter().find(|(option, _)| *option == name).copied()
+}
+
+/// Check a value has the type and range its option takes
+fn validate(name: &str, kind: Kind, value: &Value) -> Result<(), String> {
+    match (kind, value) {
+        (Kind::Int(min, max), Value::Integer(n)) if (min..=max).contains(n) => Ok(()),
+        (Kind::Int(min, max), _) => Err(format!("`{}` must be a whole number from {} to {}", name, min, max)),
+        (Kind::Bool, Value::Boolean(_)) => Ok(()),
+        (Kind::Bool, _) => Err(format!("`{}` must be true or false", name)),
+        (Kind::Str, Value::String(_)) => Ok(()),
+        (Kind::Str, _) => Err(format!("`{}` must be a string", name)),
+        (Kind::Theme, Value::String(theme)) if Theme::by_name(theme).is_some() => Ok(()),
+        (Kind::Theme, _) => Err(format!("`{}` must be one of {}", name, Theme::names())),
+    }
+}
+
+/**
+ * Parse a `:set` command for a config option
+ *
+ * Returns None if `option` isn't one, otherwise the option's name and the
+ * new value (None when the value is only asked for). Booleans are set with
+ * `:set name` and cleared with `:set noname`.
+ */
+pub fn parse_set(option: &str, value: Option<&str>) -> Option<Result<(&'static str, Option<Value>), String>> {
+    let negated = option.strip_prefix("no").and_then(self::option).filter(|(_, kind)| matches!(kind, Kind::Bool));
+    let (name, kind) = self::option(option).or(negated)?;
+
+    let parsed = match (kind, value) {
+        (Kind::Bool, None) => Value::Boolean(negated.is_none()),
+        (_, None) => return Some(Ok((name, None))),
+        (Kind::Bool, Some(value)) => match value {
+            "true" | "on" | "yes" => Value::Boolean(true),
+            "false" | "off" | "no" => Value::Boolean(false),
+            _ => return Some(Err(format!("`{}` must be true or false", name))),
+        },
+        (Kind::Int(..), Some(value)) => match value.parse::<i64>() {
+            Ok(n) => Value::Integer(n),
+            Err(_) => return Some(Err(format!("`{}` must be a whole number", name))),
+        },
+        (Kind::Str | Kind::Theme, Some(value)) => Value::String(value.to_string()),
+    };
+    Some(validate(name, kind, &parsed).map(|_| (name, Some(parsed))))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+///
+/// ### The options set by one config file
+///
+struct Layer {
+    options: Table,
+    /// Options from the `[filetype.<name>]` tables
+    filetypes: HashMap<String, Table>,
+}
+
+impl Layer {
+    /**
+     * Read and validate a config file
+     *
+     * Invalid options are left out and reported, the rest of the file is
+     * still used. A file that isn't valid TOML gives no layer at all.
+     */
+    fn read(path: &Path, contents: &str) -> (Option<Layer>, Vec<String>) {
+        let table: Table = match contents.parse() {
+            Ok(table) => table,
+            Err(err) => {
+                let err: toml::de::Error = err;
+                let line = err.span().map_or(1, |span| contents[..span.start].matches('\n').count() + 1);
+                let message = err.message().trim().replace('\n', ", ");
+                return (None, vec![format!("{}:{}: {}", path.display(), line, message)]);
+            }
+        };
+
+        let mut errors = Vec::new();
+        let mut layer = Layer { options: Table::new(), filetypes: HashMap::new() };
+        for (key, value) in table {
+            if key != "filetype" {
+                if let Some(options) = validate_options(path, "", [(key, value)].into_iter().collect(), &mut errors) {
+                    layer.options.extend(options);
+                }
+                continue;
+            }
+
+            let Value::Table(filetypes) = value else {
+                errors.push(format!("{}: `filetype` must be a table, like [filetype.rust]", path.display()));
+                continue;
+            };
+            for (filetype, options) in filetypes {
+                let prefix = format!("filetype.{}.", filetype);
+                match options {
+                    Value::Table(options) => {
+                        let options = validate_options(path, &prefix, options, &mut errors).unwrap_or_default();
+                        layer.filetypes.insert(filetype, options);
+                    }
+                    _ => errors.push(format!("{}: `filetype.{}` must be a table", path.display(), filetype)),
+                }
+            }
+        }
+        (Some(layer), errors)
+    }
+
+    /// Apply the layer's options, then the ones for `filetype`
+    fn apply_to(&self, settings: &mut Settings, filetype: Option<&str>) {
+        settings.apply(&self.options);
+        if let Some(options) = filetype.and_then(|filetype| self.filetypes.get(filetype)) {
+            settings.apply(options);
+        }
+    }
+}
+
+/// Keep the valid options of `table`, under their full names
+fn validate_options(path: &Path, prefix: &str, table: Table, errors: &mut Vec<String>) -> Option<Table> {
+    let mut valid = Table::new();
+    for (key, value) in table {
+        let result = match option(&key) {
+            Some((name, kind)) => validate(name, kind, &value).map(|_| name),
+            None => Err(format!("unknown option `{}`", key)),
+        };
+        match result {
+            Ok(name) => {
+                valid.insert(name.to_string(), value);
+            }
+            Err(err) => errors.push(format!("{}: {}{}", path.display(), prefix, err)),
+        }
+    }
+    (!valid.is_empty()).then_some(valid)
+}
+
+///
+/// ### Layered configuration
+///
+/// Options come from, lowest priority first: the defaults, the global
+/// config, the user's config, the indentation guessed from the file, the
+/// `.editorconfig` files above the file, the nearest `.pte.toml` above it,
+/// and last `:set`. The `[filetype.<name>]` table of a config file applies
+/// right after the rest of that file.
+///
+pub struct Config {
+    /// The global and user config files
+    base: Vec<PathBuf>,
+    /// Every config file looked at, with when it was last changed (None if missing)
+    seen: HashMap<PathBuf, Option<SystemTime>>,
+    layers: HashMap<PathBuf, Layer>,
+    /// `.editorconfig` properties of every file looked up, until one of them changes
+    editorconfigs: HashMap<String, HashMap<String, String>>,
+    /// Options changed with `:set` or on the command line
+    overrides: Table,
+    /// Problems not shown to the user yet
+    errors: Vec<String>,
+    last_check: Instant,
+}
+
+impl Config {
+    /// `user` replaces the user's config file, as `--config` does
+    pub fn new(user: Option<PathBuf>) -> Self {
+        let mut base = Vec::new();
+        #[cfg(unix)]
+        base.push(PathBuf::from(GLOBAL_FILE));
+        if let Some(path) = user.or_else(user_config_path) {
+            base.push(path);
+        }
+
+        Self {
+            base,
+            seen: HashMap::new(),
+            layers: HashMap::new(),
+            editorconfigs: HashMap::new(),
+            overrides: Table::new(),
+            errors: Vec::new(),
+            last_check: Instant::now(),
+        }
+    }
+
+    /// Read the global and user config files
+    pub fn load(&mut self) {
+        for path in self.base.clone() {
+            self.read(&path);
+        }
+    }
+
+    /// (Re-)read one config file, keeping the old layer if the new one is broken
+    fn read(&mut self, path: &Path) {
+        self.seen.insert(path.to_path_buf(), modified(path));
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                self.layers.remove(path);
+                return;
+            }
+            Err(err) => {
+                self.errors.push(format!("{}: {}", path.display(), err));
+                return;
+            }
+        };
+
+        let (layer, errors) = Layer::read(path, &contents);
+        if let Some(layer) = layer {
+            self.layers.insert(path.to_path_buf(), layer);
+        }
+        self.errors.extend(errors);
+    }
+
+    /// Settings for a buffer editing `filename`, reading its project config if needed
+    pub fn settings(&mut self, filename: &str, detected: Indent) -> Settings {
+        let project = project_file(filename);
+        if let Some(project) = project.as_ref().filter(|project| !self.seen.contains_key(*project)) {
+            self.read(project);
+        }
+        if !self.editorconfigs.contains_key(filename) {
+            let (properties, read) = editorconfig::properties(filename);
+            for path in read {
+                let stamp = modified(&path);
+                self.seen.entry(path).or_insert(stamp);
+            }
+            self.editorconfigs.insert(filename.to_string(), properties);
+        }
+        let properties = &self.editorconfigs[filename];
+        let filetype = language::filetype(filename);
+
+        let mut settings = Settings::new();
+        for layer in self.base.iter().filter_map(|path| self.layers.get(path)) {
+            layer.apply_to(&mut settings, filetype);
+        }
+        detected.apply(&mut settings);
+        editorconfig::apply(&mut settings, properties);
+        if let Some(layer) = project.and_then(|project| self.layers.get(&project)) {
+            layer.apply_to(&mut settings, filetype);
+        }
+        settings.apply(&self.overrides);
+        settings
+    }
+
+    /// Override an option for every buffer, `value` is validated by `parse_set`
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.overrides.insert(name.to_string(), value);
+    }
+
+    /// Problems found since the last call
+    pub fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Re-read config files that changed on disk, returning whether any did
+    fn reload_changed(&mut self) -> bool {
+        let changed: Vec<PathBuf> = self.seen.iter()
+            .filter(|(path, stamp)| modified(path) != **stamp)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in &changed {
+            // .editorconfig files are read again the next time settings() needs them
+            if path.file_name().is_some_and(|name| name == editorconfig::FILE_NAME) {
+                self.seen.insert(path.clone(), modified(path));
+                self.editorconfigs.clear();
+            } else {
+                self.read(path);
+            }
+        }
+        !changed.is_empty()
+    }
+}
+
+/// Where the user's config file is, `$XDG_CONFIG_HOME/pte/config.toml`
+pub fn user_config_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("pte").join("config.toml"))
+}
+
+/// The nearest `.pte.toml` above `filename`, or above the working directory for unnamed buffers
+fn project_file(filename: &str) -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    let start = if filename.is_empty() {
+        cwd
+    } else {
+        cwd.join(filename).parent()?.to_path_buf()
+    };
+    start.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+/**
+ * Read a config file on its own and report every problem in it, used for
+ * files named on the command line, which have to exist
+ */
+pub fn check(path: &Path) -> Vec<String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Layer::read(path, &contents).1,
+        Err(err) => vec![format!("{}: {}", path.display(), err)],
+    }
+}
+
+/**
+ * Pick up changes to config files and show problems found in them
+ */
+pub fn update(editor: &mut Editor) {
+    if editor.config.last_check.elapsed() >= RELOAD_INTERVAL {
+        editor.config.last_check = Instant::now();
+        if editor.config.reload_changed() {
+            editor.reapply_settings();
+            editor.notif_text = String::from("Config reloaded");
+        }
+    }
+
+    if let Some(error) = editor.config.take_errors().first() {
+        editor.notif_text = format!("Config error: {}", error);
+    }
+}
@@ -0,0 +1,78 @@
+/// Past this many (old * new) line pairs only the changed middle is shown
+/// as one removed and one added block, to keep the table small
+const MAX_TABLE: usize = 4_000_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+///
+/// ### A diff shown in a popup, with how far it's scrolled
+///
+pub struct DiffView {
+    pub title: String,
+    pub diff: Vec<DiffLine>,
+    pub scroll: usize,
+}
+
+/// Line diff turning `old` into `new`, based on the longest common subsequence
+pub fn diff_lines(old: &[String], new: &[String]) -> Vec<DiffLine> {
+    // common prefix and suffix don't need the table
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut out: Vec<DiffLine> = old[..prefix].iter().cloned().map(DiffLine::Same).collect();
+
+    if old_mid.len() * new_mid.len() > MAX_TABLE {
+        out.extend(old_mid.iter().cloned().map(DiffLine::Removed));
+        out.extend(new_mid.iter().cloned().map(DiffLine::Added));
+    } else {
+        out.extend(diff_table(old_mid, new_mid));
+    }
+
+    out.extend(old[old.len() - suffix..].iter().cloned().map(DiffLine::Same));
+    out
+}
+
+fn diff_table(old: &[String], new: &[String]) -> Vec<DiffLine> {
+    // lcs[i][j] is the LCS length of old[i..] and new[j..]
+    let width = new.len() + 1;
+    let mut lcs = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i * width + j] = if old[i] == new[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut out = Vec::with_capacity(old.len() + new.len());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            out.push(DiffLine::Same(old[i].clone()));
+            i += 1;
+            j += 1;
+        } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+            out.push(DiffLine::Removed(old[i].clone()));
+            i += 1;
+        } else {
+            out.push(DiffLine::Added(new[j].clone()));
+            j += 1;
+        }
+    }
+    out.extend(old[i..].iter().cloned().map(DiffLine::Removed));
+    out.extend(new[j..].iter().cloned().map(DiffLine::Added));
+    out
+}
+
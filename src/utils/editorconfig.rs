@@ -0,0 +1,367 @@
+use super::{config::Settings, encoding, format::LineEnding};
+use encoding_rs::UTF_8;
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::PathBuf
+};
+
+pub const FILE_NAME: &str = ".editorconfig";
+
+///
+/// ### A section name of an `.editorconfig` file
+///
+/// Globs follow the EditorConfig rules: `*` doesn't cross a `/`, `**` does,
+/// `{a,b}` picks one of several and `{1..10}` matches a number in a range.
+/// A glob without a `/` matches the file name in any directory below the
+/// `.editorconfig`, otherwise it's matched from that directory.
+///
+struct Glob {
+    regex: Regex,
+    /// Bounds of every `{n1..n2}`, in the order of the regex's groups
+    ranges: Vec<(i64, i64)>,
+}
+
+impl Glob {
+    fn new(pattern: &str) -> Option<Self> {
+        let chars: Vec<char> = pattern.strip_prefix('/').unwrap_or(pattern).chars().collect();
+        let mut ranges = Vec::new();
+        let body = translate(&chars, &mut ranges);
+        let anchored = match pattern.contains('/') {
+            true => format!("^{}$", body),
+            false => format!("^(?:.*/)?{}$", body),
+        };
+        Regex::new(&anchored).ok().map(|regex| Self { regex, ranges })
+    }
+
+    /// `path` is relative to the directory of the `.editorconfig`, with `/` separators
+    fn matches(&self, path: &str) -> bool {
+        let Some(captures) = self.regex.captures(path) else {
+            return false;
+        };
+        self.ranges.iter().enumerate().all(|(i, (min, max))| match captures.get(i + 1) {
+            Some(number) => number.as_str().parse::<i64>().is_ok_and(|n| (*min..=*max).contains(&n)),
+            None => true,
+        })
+    }
+}
+
+/// Turn a glob into a regex, adding a group to `ranges` for every numeric range
+fn translate(chars: &[char], ranges: &mut Vec<(i64, i64)>) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                out.push_str(&regex::escape(&chars[i].to_string()));
+            }
+            // "a/**/b" also matches "a/b"
+            '/' if chars[i + 1..].starts_with(&['*', '*', '/']) => {
+                out.push_str("(?:/.*)?/");
+                i += 3;
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                out.push_str(".*");
+                i += 1;
+            }
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            '[' => match chars[i + 1..].iter().position(|c| *c == ']') {
+                Some(len) if len > 0 && !chars[i + 1..i + 1 + len].contains(&'/') => {
+                    out.push('[');
+                    for (n, c) in chars[i + 1..i + 1 + len].iter().enumerate() {
+                        match c {
+                            '!' if n == 0 => out.push('^'),
+                            '-' if n > 0 => out.push('-'),
+                            c => out.push_str(&regex::escape(&c.to_string())),
+                        }
+                    }
+                    out.push(']');
+                    i += len + 1;
+                }
+                _ => out.push_str("\\["),
+            },
+            '{' => match closing_brace(&chars[i..]) {
+                Some(len) => {
+                    let inner = &chars[i + 1..i + len];
+                    out.push_str(&translate_braces(inner, ranges));
+                    i += len;
+                }
+                None => out.push_str("\\{"),
+            },
+            c => out.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    out
+}
+
+/// Translate what's inside `{...}`: a numeric range, alternatives, or literal text
+fn translate_braces(inner: &[char], ranges: &mut Vec<(i64, i64)>) -> String {
+    let text: String = inner.iter().collect();
+    if let Some((min, max)) = text.split_once("..") {
+        if let (Ok(min), Ok(max)) = (min.parse::<i64>(), max.parse::<i64>()) {
+            ranges.push((min.min(max), min.max(max)));
+            return String::from("([+-]?[0-9]+)");
+        }
+    }
+
+    // split on the commas that aren't inside a nested {...}
+    let mut alternatives = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in inner.iter().enumerate() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    alternatives.push(&inner[start..]);
+
+    if alternatives.len() == 1 {
+        return format!("\\{{{}\\}}", translate(inner, ranges));
+    }
+    let alternatives: Vec<String> = alternatives.iter().map(|alternative| translate(alternative, ranges)).collect();
+    format!("(?:{})", alternatives.join("|"))
+}
+
+/// Length up to the `}` matching the `{` at the start of `chars`
+fn closing_brace(chars: &[char]) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in chars.iter().enumerate() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+///
+/// ### One parsed `.editorconfig` file
+///
+struct File {
+    /// No `.editorconfig` above this one is read
+    root: bool,
+    /// Sections in file order, each with its properties
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl File {
+    /**
+     * Parse an INI style `.editorconfig`
+     *
+     * Names are case-insensitive and so are the values of the properties
+     * we know, lines that don't parse are skipped like other editors do.
+     */
+    fn parse(contents: &str) -> Self {
+        let mut file = File { root: false, sections: Vec::new() };
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['#', ';']) {
+                continue;
+            }
+
+            if let Some(section) = line.strip_prefix('[').and_then(|rest| rest.rsplit_once(']')) {
+                file.sections.push((section.0.to_string(), Vec::new()));
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let (key, value) = (key.trim().to_lowercase(), value.trim().to_lowercase());
+            match file.sections.last_mut() {
+                Some((_, properties)) => properties.push((key, value)),
+                None if key == "root" => file.root = value == "true",
+                None => {}
+            }
+        }
+        file
+    }
+}
+
+/**
+ * The properties `.editorconfig` files give `filename`, and the files read
+ *
+ * Files are looked for from the file's directory upwards until one says
+ * `root = true`. Nearer files win over ones further up, and later sections
+ * over earlier ones. Properties set to `unset` are left out.
+ */
+pub fn properties(filename: &str) -> (HashMap<String, String>, Vec<PathBuf>) {
+    let mut properties = HashMap::new();
+    let mut read = Vec::new();
+    let Some(path) = (!filename.is_empty()).then(|| env::current_dir().ok()).flatten().map(|cwd| cwd.join(filename)) else {
+        return (properties, read);
+    };
+
+    let mut files = Vec::new();
+    for dir in path.ancestors().skip(1) {
+        let config = dir.join(FILE_NAME);
+        let Ok(contents) = fs::read_to_string(&config) else {
+            continue;
+        };
+        let file = File::parse(&contents);
+        let root = file.root;
+        files.push((dir.to_path_buf(), file));
+        read.push(config);
+        if root {
+            break;
+        }
+    }
+
+    for (dir, file) in files.iter().rev() {
+        let Ok(relative) = path.strip_prefix(dir) else {
+            continue;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        for (section, values) in &file.sections {
+            if !Glob::new(section).is_some_and(|glob| glob.matches(&relative)) {
+                continue;
+            }
+            for (key, value) in values {
+                properties.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    properties.retain(|_, value| value != "unset");
+    (properties, read)
+}
+
+/**
+ * Apply the properties we support to `settings`
+ *
+ * `indent_size` is the width of an indent level and `tab_width` the width
+ * of a tab, the editor has one width so the one that fits `indent_style` is
+ * used. Values we don't understand are ignored.
+ */
+pub fn apply(settings: &mut Settings, properties: &HashMap<String, String>) {
+    let get = |name: &str| properties.get(name).map(String::as_str);
+    let number = |name: &str| get(name).and_then(|value| value.parse::<usize>().ok());
+
+    match get("indent_style") {
+        Some("tab") => settings.hard_tabs = true,
+        Some("space") => settings.hard_tabs = false,
+        _ => {}
+    }
+    // `indent_size = tab` indents by a tab's width
+    let indent_size = match get("indent_size") {
+        Some("tab") => number("tab_width"),
+        _ => number("indent_size"),
+    };
+    let width = match settings.hard_tabs {
+        true => number("tab_width").or(indent_size),
+        false => indent_size.or_else(|| number("tab_width")),
+    };
+    if let Some(width) = width.filter(|width| (1..=16).contains(width)) {
+        settings.tab_width = width;
+    }
+
+    if let Some(line_ending) = get("end_of_line").and_then(LineEnding::from_name) {
+        settings.end_of_line = Some(line_ending);
+    }
+    settings.charset = match get("charset") {
+        Some("utf-8-bom") => Some((UTF_8, true)),
+        Some("utf-8") => Some((UTF_8, false)),
+        Some(label) => encoding::from_label(label).map(|encoding| (encoding, false)),
+        None => settings.charset,
+    };
+    match get("trim_trailing_whitespace") {
+        Some("true") => settings.trim_trailing_whitespace = true,
+        Some("false") => settings.trim_trailing_whitespace = false,
+        _ => {}
+    }
+    match get("insert_final_newline") {
+        Some("true") => settings.final_newline = Some(true),
+        Some("false") => settings.final_newline = Some(false),
+        _ => {}
+    }
+    match get("max_line_length") {
+        Some("off") => settings.max_line_length = 0,
+        _ => {
+            if let Some(length) = number("max_line_length") {
+                settings.max_line_length = length;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).is_some_and(|glob| glob.matches(path))
+    }
+
+    #[test]
+    fn star_stays_in_one_directory() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", "src/editor/main.rs"));
+        assert!(!matches("src/*.rs", "src/editor/main.rs"));
+        assert!(matches("src/**.rs", "src/editor/main.rs"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+    }
+
+    #[test]
+    fn slash_anchors_to_the_directory() {
+        assert!(matches("/Makefile", "Makefile"));
+        assert!(matches("docs/*.md", "docs/index.md"));
+        assert!(!matches("docs/*.md", "src/docs/index.md"));
+    }
+
+    #[test]
+    fn braces_and_classes() {
+        assert!(matches("*.{js,ts}", "app.ts"));
+        assert!(!matches("*.{js,ts}", "app.rs"));
+        assert!(matches("file{1..10}.txt", "file7.txt"));
+        assert!(!matches("file{1..10}.txt", "file11.txt"));
+        assert!(matches("[ab].txt", "b.txt"));
+        assert!(!matches("[!ab].txt", "b.txt"));
+        assert!(matches("{single}.txt", "{single}.txt"));
+    }
+
+    #[test]
+    fn later_sections_and_nearer_files_win() {
+        let root = env::temp_dir().join(format!("pte-editorconfig-{}", std::process::id()));
+        let nested = root.join("nested");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join(FILE_NAME), "root = true\n[*]\nindent_size = 2\nindent_style = tab\n[*.rs]\nindent_size = 4\n").unwrap();
+        fs::write(nested.join(FILE_NAME), "[*.rs]\nindent_style = space\ntab_width = unset\n").unwrap();
+
+        let (properties, read) = properties(&nested.join("main.rs").to_string_lossy());
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(read.len(), 2);
+        assert_eq!(properties.get("indent_size").map(String::as_str), Some("4"));
+        assert_eq!(properties.get("indent_style").map(String::as_str), Some("space"));
+        assert!(!properties.contains_key("tab_width"));
+    }
+
+    #[test]
+    fn indent_size_tab_uses_tab_width() {
+        let properties = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+        };
+        let mut settings = Settings::new();
+        apply(&mut settings, &properties(&[("indent_style", "space"), ("indent_size", "tab"), ("tab_width", "8")]));
+        assert_eq!(settings.tab_width, 8);
+
+        let mut settings = Settings::new();
+        let before = settings.tab_width;
+        apply(&mut settings, &properties(&[("indent_size", "tab")]));
+        assert_eq!(settings.tab_width, before);
+    }
+}
@@ -0,0 +1,224 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{DecoderResult, EncoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// Bytes that can't be decoded are kept as `ESCAPE_BASE + byte`, a range of
+/// private use characters, so they are written back untouched on save
+const ESCAPE_BASE: u32 = 0x10FF00;
+
+/// How decoding dealt with the contents of a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decoded {
+    /// Every byte was valid
+    Clean,
+    /// Some bytes weren't valid and are kept as escaped bytes
+    Escaped,
+    /// Some characters are in the escape range, saving would turn them into bytes
+    Ambiguous,
+}
+
+/// How many bytes to look at when guessing the encoding
+const SNIFF_LEN: usize = 64 * 1024;
+
+/// Look up an encoding by a label like `utf-8`, `latin1` or `shift_jis`
+pub fn from_label(label: &str) -> Option<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+/// Whether the encoding can store every character (and a BOM)
+pub fn is_unicode(encoding: &'static Encoding) -> bool {
+    encoding == UTF_8 || encoding == UTF_16LE || encoding == UTF_16BE
+}
+
+/// Guess the encoding of file contents
+///
+/// A BOM wins, then valid UTF-8, then UTF-16 without a BOM (lots of zero
+/// bytes in every other position), and finally a statistical guess.
+pub fn detect(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+    if let Some(encoding) = detect_utf16(sample) {
+        return encoding;
+    }
+    if mostly_utf8(bytes) {
+        return UTF_8;
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(sample, sample.len() == bytes.len());
+    let guess = detector.guess(None, true);
+
+    // a guess that can't decode the file is worse than UTF-8 with a few bad bytes
+    let (_, _, had_errors) = guess.decode(bytes);
+    if had_errors { UTF_8 } else { guess }
+}
+
+/// Valid UTF-8, or UTF-8 with fewer bad bytes than multi-byte characters
+///
+/// Legacy encodings rarely form valid multi-byte sequences by accident, so
+/// this catches UTF-8 files with a few stray bytes in them.
+fn mostly_utf8(bytes: &[u8]) -> bool {
+    let (mut multibyte, mut invalid) = (0, 0);
+    let mut rest = bytes;
+    loop {
+        let (valid, error) = match std::str::from_utf8(rest) {
+            Ok(valid) => (valid, None),
+            Err(err) => (
+                std::str::from_utf8(&rest[..err.valid_up_to()]).expect("checked by from_utf8"),
+                Some(err),
+            ),
+        };
+        multibyte += valid.chars().filter(|c| c.len_utf8() > 1).count();
+
+        match error {
+            None => return invalid == 0 || invalid < multibyte,
+            Some(err) => {
+                let bad = err.error_len().unwrap_or(rest.len() - err.valid_up_to());
+                invalid += bad;
+                rest = &rest[err.valid_up_to() + bad..];
+            }
+        }
+    }
+}
+
+fn detect_utf16(sample: &[u8]) -> Option<&'static Encoding> {
+    let pairs = sample.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+
+    let even = sample.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd = sample.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+
+    // mostly-ASCII text has a zero high byte for nearly every character
+    if odd * 10 > pairs * 4 && even * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even * 10 > pairs * 4 && odd * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Decode file contents, keeping a leading BOM as `U+FEFF`
+///
+/// Returns the text and whether any bytes had to be escaped, or whether
+/// the file has characters that can't be told apart from escaped bytes.
+pub fn decode(bytes: &[u8], encoding: &'static Encoding) -> (String, Decoded) {
+    let mut text = String::with_capacity(bytes.len());
+    let mut escaped = false;
+    let mut ambiguous = false;
+
+    let bom_len = match Encoding::for_bom(bytes) {
+        Some((bom_encoding, len)) if bom_encoding == encoding => len,
+        _ => 0,
+    };
+    if bom_len > 0 {
+        text.push('\u{feff}');
+    }
+
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let mut input = &bytes[bom_len..];
+    loop {
+        let needed = decoder.max_utf8_buffer_length_without_replacement(input.len()).unwrap_or(input.len() * 3 + 16);
+        text.reserve(needed);
+
+        let start = text.len();
+        let (result, read) = decoder.decode_to_string_without_replacement(input, &mut text, true);
+        ambiguous |= text[start..].chars().any(|c| unescape(c).is_some());
+        match result {
+            DecoderResult::InputEmpty => break,
+            DecoderResult::OutputFull => {}
+            DecoderResult::Malformed(bad, extra) => {
+                // the malformed bytes are the last ones read, minus `extra`
+                let end = read.saturating_sub(extra as usize);
+                let start = end.saturating_sub(bad as usize);
+                for byte in &input[start..end] {
+                    text.push(escape(*byte));
+                }
+                escaped = true;
+            }
+        }
+        input = &input[read..];
+    }
+
+    let decoded = if ambiguous {
+        Decoded::Ambiguous
+    } else if escaped {
+        Decoded::Escaped
+    } else {
+        Decoded::Clean
+    };
+    (text, decoded)
+}
+
+/// Encode text for writing, turning escaped bytes back into the raw bytes
+///
+/// Fails with the first character the encoding can't represent.
+pub fn encode(text: &str, encoding: &'static Encoding) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(text.len());
+
+    // encoding_rs only decodes UTF-16, so it's written by hand
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        for c in text.chars() {
+            if let Some(byte) = unescape(c) {
+                out.push(byte);
+                continue;
+            }
+            let mut units = [0u16; 2];
+            for unit in c.encode_utf16(&mut units) {
+                if encoding == UTF_16LE {
+                    out.extend_from_slice(&unit.to_le_bytes());
+                } else {
+                    out.extend_from_slice(&unit.to_be_bytes());
+                }
+            }
+        }
+        return Ok(out);
+    }
+
+    let mut encoder = encoding.new_encoder();
+    let mut rest = text;
+    while !rest.is_empty() {
+        // encode up to the next escaped byte
+        let (run, next) = match rest.char_indices().find(|(_, c)| unescape(*c).is_some()) {
+            Some((index, c)) => (&rest[..index], Some((c, index + c.len_utf8()))),
+            None => (rest, None),
+        };
+
+        let mut input = run;
+        loop {
+            let needed = encoder.max_buffer_length_from_utf8_without_replacement(input.len()).unwrap_or(input.len() * 4 + 16);
+            out.reserve(needed);
+            let (result, read) = encoder.encode_from_utf8_to_vec_without_replacement(input, &mut out, next.is_none());
+            match result {
+                EncoderResult::InputEmpty => break,
+                EncoderResult::OutputFull => input = &input[read..],
+                EncoderResult::Unmappable(c) => {
+                    return Err(format!("'{}' can't be saved as {}", c, encoding.name()));
+                }
+            }
+        }
+
+        match next {
+            Some((c, end)) => {
+                out.push(unescape(c).expect("found an escaped byte"));
+                rest = &rest[end..];
+            }
+            None => break,
+        }
+    }
+
+    Ok(out)
+}
+
+fn escape(byte: u8) -> char {
+    char::from_u32(ESCAPE_BASE + byte as u32).expect("escape range is valid")
+}
+
+fn unescape(c: char) -> Option<u8> {
+    let code = c as u32;
+    (code >= ESCAPE_BASE).then(|| (code - ESCAPE_BASE) as u8)
+}
@@ -1,70 +1,328 @@
-use crate::Editor;
+use crate::{editor::{indent::{self, Indent}, large::{self, LargeFile}}, Editor};
+use super::{
+    config::Settings,
+    encoding::{self, Decoded},
+    format::{self, FileFormat},
+    swap
+};
+use encoding_rs::{Encoding, UTF_8};
 use std::{
-    fs::OpenOptions, 
-    io::{Read, Write},
-    path::Path
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process
 };
 
 /**
  * Opens the file specified in the editor.filename field
+ *
+ * Returns false if it exists but couldn't be read, the notif says why.
  */
-pub fn open_file(editor: &mut Editor) {
-    let file_path = Path::new(&editor.filename);
-    let file_exists = file_path.exists();
-
-    let file = OpenOptions::new().read(true).write(true).create(true).open(&editor.filename);
-    match file {
-        Ok(mut file) => {
-
-            //read contents of the file
-            let mut contents = String::new();
-            let read = file.read_to_string(&mut contents);
-            match read {
-                Ok(_) => {
-                    editor.text = contents;
-                    editor.lines = editor.text.lines().map(|s| s.to_string()).collect();
-                    editor.notif_text = String::from("Edit mode");
-                    editor.command_mode = false;
-                    editor.history.clear();
-
-                    editor.cursors = vec![editor.cursors[0].clone()];
-                    editor.cursors[0].line = 0;
-                    editor.cursors[0].col = 0;
-                }
-                Err(_) => {
-                    editor.text = String::from("Error reading file");
-                    editor.notif_text = String::from("Error reading file");
-                }
-            }
-        },
-        Err(_) => {
-            editor.notif_text = String::from("Failed to open file!");
-            editor.lines = Vec::new();
-            editor.lines.push(String::from("Failed to open file!"));
+pub fn open_file(editor: &mut Editor) -> bool {
+    open_file_with_encoding(editor, None)
+}
+
+/**
+ * Opens the file specified in the editor.filename field, decoding it as
+ * `forced` instead of guessing the encoding when it is given
+ */
+pub fn open_file_with_encoding(editor: &mut Editor, forced: Option<&'static Encoding>) -> bool {
+    editor.large = None;
+    editor.detected_indent = Indent::new();
+    editor.apply_settings();
+    let size = fs::metadata(&editor.filename).map(|metadata| metadata.len()).unwrap_or(0);
+    // even with an encoding given, reading it all into memory is what large mode avoids
+    if size >= editor.settings.large_file_threshold() {
+        let opened = open_large_file(editor);
+        if opened && forced.is_some_and(|encoding| encoding != UTF_8) {
+            editor.notif_text = String::from("Large files are always read as UTF-8");
         }
+        return opened;
     }
 
+    // only read here, the file is created (or written) when it is saved
+    let mut bytes = Vec::new();
+    let file_exists = match fs::File::open(&editor.filename).and_then(|mut file| file.read_to_end(&mut bytes)) {
+        Ok(_) => true,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => false,
+        Err(err) => {
+            editor.notif_text = format!("Can't open {}: {}", editor.filename, err);
+            return false;
+        }
+    };
+
+    let charset = editor.settings.charset.map(|(encoding, _)| encoding);
+    let file_encoding = forced.or(charset).unwrap_or_else(|| encoding::detect(&bytes));
+    let (contents, decoded) = encoding::decode(&bytes, file_encoding);
+    let (lines, mut file_format) = format::decode(&contents);
+    file_format.encoding = file_encoding;
+    if editor.settings.detect_indent {
+        editor.detected_indent = indent::detect(&lines);
+        editor.apply_settings();
+    }
     if !file_exists {
-        editor.notif_text = String::from("New file created");
+        file_format = FileFormat::new();
     }
+    let detected = file_format.clone();
+    follow_editorconfig(&mut file_format, &editor.settings, forced.is_some());
+
+    editor.text = contents;
+    editor.lines = lines;
+    editor.notif_text = if !file_exists {
+        String::from("New file")
+    } else if decoded == Decoded::Ambiguous {
+        String::from("U+10FF00..U+10FFFF can't be saved, read-only mode")
+    } else if decoded == Decoded::Escaped {
+        format!("Some bytes aren't valid {}, they are kept as-is", file_encoding.name())
+    } else if detected.mixed_endings {
+        format!("Mixed line endings, saving as {}", file_format.line_ending)
+    } else if file_format != detected {
+        format!("Saving as {} for .editorconfig", file_format.describe())
+    } else {
+        String::from("Edit mode")
+    };
+    editor.format = file_format;
+    opened(editor, file_exists);
+    // those would be written back as the bytes they stand for
+    if decoded == Decoded::Ambiguous {
+        editor.read_only = true;
+    }
+
+    if let Some(recovery) = swap::find_recovery(editor) {
+        editor.recoveries.retain(|r| r.swap_path != recovery.swap_path);
+        editor.recoveries.push(recovery);
+    }
+    true
+}
+
+/**
+ * Change how a file is saved to what its `.editorconfig` asks for
+ *
+ * The encoding is left alone when the user picked one.
+ */
+fn follow_editorconfig(file_format: &mut FileFormat, settings: &Settings, forced: bool) {
+    if let Some(line_ending) = settings.end_of_line {
+        file_format.line_ending = line_ending;
+        file_format.mixed_endings = false;
+    }
+    if let Some(final_newline) = settings.final_newline {
+        file_format.trailing_newline = final_newline;
+    }
+    if let Some((charset, bom)) = settings.charset.filter(|_| !forced) {
+        file_format.encoding = charset;
+        file_format.bom = bom && encoding::is_unicode(charset);
+    }
+}
+
+/**
+ * Reads all of stdin into the active buffer, which is left unnamed
+ *
+ * Like a new file, it needs a name to be saved (:w <name>).
+ */
+pub fn read_stdin(editor: &mut Editor) {
+    let mut bytes = Vec::new();
+    if let Err(err) = io::stdin().lock().read_to_end(&mut bytes) {
+        editor.notif_text = format!("Can't read stdin: {}", err);
+        return;
+    }
+
+    let stdin_encoding = encoding::detect(&bytes);
+    let (contents, decoded) = encoding::decode(&bytes, stdin_encoding);
+    let (lines, mut stdin_format) = format::decode(&contents);
+    stdin_format.encoding = stdin_encoding;
+
+    editor.large = None;
+    editor.filename = String::new();
+    editor.apply_settings();
+    editor.notif_text = format!("Read {} lines from stdin", lines.len());
+    editor.text = contents;
+    editor.lines = lines;
+    editor.format = stdin_format;
+    opened(editor, false);
+    if decoded == Decoded::Ambiguous {
+        editor.read_only = true;
+        editor.notif_text = String::from("U+10FF00..U+10FFFF can't be saved, read-only mode");
+    }
+}
+
+/**
+ * Writes the active buffer to stdout, in the format it was read with
+ */
+pub fn write_stdout(editor: &Editor) -> io::Result<()> {
+    if editor.large.is_some() {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "large files can't be written to stdout"));
+    }
+
+    let contents = format::encode(&editor.lines, &editor.format);
+    let bytes = encoding::encode(&contents, editor.format.encoding)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&bytes)?;
+    stdout.flush()
+}
+
+/**
+ * Opens editor.filename without reading it into memory
+ *
+ * Encoding detection is skipped for these, the file is read as UTF-8.
+ */
+fn open_large_file(editor: &mut Editor) -> bool {
+    match LargeFile::open(Path::new(&editor.filename)) {
+        Ok((large_file, lines, file_format)) => {
+            editor.text = String::new();
+            editor.lines = lines;
+            editor.format = file_format;
+            editor.large = Some(large_file);
+            editor.notif_text = String::from("Large file, some features are turned off");
+            opened(editor, true);
+
+            if let Some(recovery) = swap::find_recovery(editor) {
+                editor.recoveries.retain(|r| r.swap_path != recovery.swap_path);
+                editor.recoveries.push(recovery);
+            }
+            true
+        }
+        Err(err) => {
+            editor.notif_text = format!("Can't open {}: {}", editor.filename, err);
+            false
+        }
+    }
+}
+
+/// Reset the editor state that every freshly opened file starts with
+fn opened(editor: &mut Editor, file_exists: bool) {
+    let writable = !file_exists || is_writable(Path::new(&editor.filename));
+    editor.read_only = editor.readonly_mode || !writable;
+    if editor.read_only && editor.notif_text == "Edit mode" {
+        editor.notif_text = String::from(if writable { "Read-only mode" } else { "File is read-only" });
+    }
+    editor.command_mode = false;
+    editor.history.clear();
+    editor.history.mark_saved();
+    editor.changes_saved = true;
+    editor.scroll = 0;
+
+    editor.cursors = vec![editor.cursors[0].clone()];
+    editor.cursors[0].line = 0;
+    editor.cursors[0].col = 0;
+
+    if !editor.filename.is_empty() {
+        editor.recent_files.retain(|f| f != &editor.filename);
+        editor.recent_files.push(editor.filename.clone());
+    }
+}
+
+/// Whether we may write to an existing file
+///
+/// Opening it for appending checks ownership and ACLs too, not just the
+/// mode bits, and doesn't change the file.
+fn is_writable(path: &Path) -> bool {
+    OpenOptions::new().append(true).open(path).is_ok()
 }
 
 
 /**
  * Saves the file specified in the editor.filename field
  */
-pub fn save_file(editor: &Editor) {
-    let file = std::fs::File::create(&editor.filename);
-
-    let content = editor.lines.join("\n");
-    match file {
-        Ok(mut file) => {
-            let write = file.write_all(content.as_bytes());
-            match write {
-                Ok(_) => {}
-                Err(_) => {}
+pub fn save_file(editor: &mut Editor) -> io::Result<()> {
+    let filename = editor.filename.clone();
+    save_file_as(editor, &filename)
+}
+
+/**
+ * Writes the editor contents to the given path, without changing editor.filename
+ *
+ * The contents are written to a temporary file next to the target, synced
+ * to disk and then renamed over it, so a failed save never leaves a
+ * truncated file behind.
+ */
+pub fn save_file_as(editor: &mut Editor, filename: &str) -> io::Result<()> {
+    if filename.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no file name"));
+    }
+    if editor.large.is_some() {
+        large::save(editor, filename)?;
+        editor.watcher.refresh(filename);
+    } else {
+        // writing a copy elsewhere trims the copy, not the buffer
+        let trimmed: Vec<String>;
+        let lines = if !editor.settings.trim_trailing_whitespace {
+            &editor.lines
+        } else if filename == editor.filename {
+            editor.trim_trailing_whitespace();
+            &editor.lines
+        } else {
+            trimmed = editor.lines.iter().map(|line| line.trim_end().to_string()).collect();
+            &trimmed
+        };
+        let content = format::encode(lines, &editor.format);
+        let bytes = encoding::encode(&content, editor.format.encoding)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        write_atomic(Path::new(filename), &bytes)?;
+    }
+
+    // so undoing back to what was written counts as saved
+    if filename == editor.filename {
+        editor.history.mark_saved();
+    }
+    Ok(())
+}
+
+fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    write_atomic_with(path, |file| file.write_all(content))
+}
+
+/**
+ * Replaces `path` atomically with whatever `write` writes to the file it's given
+ */
+pub fn write_atomic_with<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut fs::File) -> io::Result<()>,
+{
+    // write through symlinks instead of replacing the link with a file
+    let target = match fs::canonicalize(path) {
+        Ok(resolved) => resolved,
+        Err(_) => path.to_path_buf(),
+    };
+    let existing = fs::metadata(&target).ok();
+
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = target.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file name"))?
+        .to_string_lossy();
+    let temp = dir.join(format!(".{}.pte-{}.tmp", name, process::id()));
+
+    let result = (|| {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&temp)?;
+        write(&mut file)?;
+        file.sync_all()?;
+
+        if let Some(metadata) = &existing {
+            fs::set_permissions(&temp, metadata.permissions())?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                // only root can give files away, so this is best effort
+                let _ = std::os::unix::fs::chown(&temp, Some(metadata.uid()), Some(metadata.gid()));
             }
         }
-        Err(_) => {}
+
+        fs::rename(&temp, &target)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+        return result;
+    }
+
+    // make the rename itself durable
+    #[cfg(unix)]
+    if let Ok(dir) = fs::File::open(&dir) {
+        let _ = dir.sync_all();
     }
+
+    Ok(())
 }
\ No newline at end of file
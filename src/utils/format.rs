@@ -0,0 +1,174 @@
+use encoding_rs::{Encoding, UTF_8};
+use std::fmt;
+
+const BOM: char = '\u{feff}';
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    /// Parse the names used by `:set fileformat`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "unix" | "lf" => Some(LineEnding::Lf),
+            "dos" | "crlf" | "windows" => Some(LineEnding::CrLf),
+            "mac" | "cr" => Some(LineEnding::Cr),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "unix",
+            LineEnding::CrLf => "dos",
+            LineEnding::Cr => "mac",
+        }
+    }
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+            LineEnding::Cr => "CR",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+///
+/// ### How a file was laid out on disk
+///
+/// Remembered when a file is opened so saving writes it back the same way.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileFormat {
+    pub line_ending: LineEnding,
+    pub trailing_newline: bool,
+    pub bom: bool,
+    /// The file used more than one kind of line ending
+    pub mixed_endings: bool,
+    /// Character encoding the file is read and written in
+    pub encoding: &'static Encoding,
+}
+
+impl FileFormat {
+    /// Format for files that don't exist yet
+    pub fn new() -> Self {
+        Self {
+            line_ending: LineEnding::Lf,
+            trailing_newline: true,
+            bom: false,
+            mixed_endings: false,
+            encoding: UTF_8,
+        }
+    }
+
+    /// Short description for the status area, e.g. `UTF-8 CRLF BOM noeol`
+    pub fn describe(&self) -> String {
+        let mut parts = vec![self.encoding.name().to_string(), self.line_ending.to_string()];
+        if self.bom {
+            parts.push(String::from("BOM"));
+        }
+        if !self.trailing_newline {
+            parts.push(String::from("noeol"));
+        }
+        if self.mixed_endings {
+            parts.push(String::from("mixed"));
+        }
+        parts.join(" ")
+    }
+}
+
+/// Split decoded file contents into lines, detecting how they were stored
+///
+/// The encoding is left as UTF-8, the caller knows what it decoded from.
+pub fn decode(contents: &str) -> (Vec<String>, FileFormat) {
+    let (bom, contents) = match contents.strip_prefix(BOM) {
+        Some(rest) => (true, rest),
+        None => (false, contents),
+    };
+
+    // count each kind of line ending to find the dominant one
+    let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+    let bytes = contents.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\r' if bytes.get(i + 1) == Some(&b'\n') => {
+                crlf += 1;
+                i += 1;
+            }
+            b'\r' => cr += 1,
+            b'\n' => lf += 1,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    let line_ending = if crlf > lf && crlf >= cr {
+        LineEnding::CrLf
+    } else if cr > lf && cr > crlf {
+        LineEnding::Cr
+    } else {
+        LineEnding::Lf
+    };
+    let mixed_endings = [lf, crlf, cr].iter().filter(|n| **n > 0).count() > 1;
+
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                lines.push(std::mem::take(&mut current));
+            }
+            '\n' => lines.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+
+    let trailing_newline = contents.ends_with('\n') || contents.ends_with('\r');
+    if !trailing_newline || lines.is_empty() {
+        lines.push(current);
+    }
+
+    let format = FileFormat {
+        line_ending,
+        trailing_newline,
+        bom,
+        mixed_endings,
+        encoding: UTF_8,
+    };
+    (lines, format)
+}
+
+/// Join lines back into file contents using `format`
+pub fn encode(lines: &[String], format: &FileFormat) -> String {
+    let mut contents = String::new();
+    if format.bom {
+        contents.push(BOM);
+    }
+
+    contents.push_str(&lines.join(format.line_ending.as_str()));
+    if format.trailing_newline {
+        contents.push_str(format.line_ending.as_str());
+    }
+    contents
+}
@@ -1,3 +1,11 @@
 pub mod files;
+#[allow(clippy::module_inception)]
 pub mod utils;
-pub mod macros;
\ No newline at end of file
+pub mod macros;
+pub mod format;
+pub mod encoding;
+pub mod diff;
+pub mod swap;
+pub mod watcher;
+pub mod config;
+pub mod editorconfig;
@@ -0,0 +1,351 @@
+use crate::{editor::large::{LargeFile, Patch}, Editor};
+use super::diff::DiffLine;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    env,
+    fs::{self, OpenOptions},
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::mpsc::{self, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant}
+};
+
+/// How often dirty buffers are written to their swap files
+const SWAP_INTERVAL: Duration = Duration::from_secs(4);
+
+const HEADER: &str = "PTE-SWAP";
+
+enum Job {
+    /// Swap file path, name of the file it's for, contents
+    Write(PathBuf, String, String),
+    Remove(PathBuf),
+}
+
+///
+/// ### Swap files for crash recovery
+///
+/// Every few seconds the contents of dirty buffers are handed to a
+/// background thread, which writes them to `.name.pte.swp` next to the
+/// file. They are removed again once the buffer is saved or closed.
+/// For a large file only its edits are written, as patches to the file.
+///
+pub struct SwapFiles {
+    sender: Option<Sender<Job>>,
+    handle: Option<JoinHandle<()>>,
+    /// Swap files this editor owns, with a hash of what was last written
+    written: HashMap<PathBuf, u64>,
+    last_update: Instant,
+}
+
+///
+/// ### A swap file left behind by an editor that didn't exit cleanly
+///
+pub struct Recovery {
+    pub filename: String,
+    pub swap_path: PathBuf,
+    pub lines: Vec<String>,
+    /// The edits, for a large file, `lines` are then their lines
+    pub patches: Option<Vec<Patch>>,
+    /// The editor that wrote the swap file, if it still seems to be running
+    pub running_pid: Option<u32>,
+    /// Shown in a popup once the user asks for it
+    pub diff: Option<Vec<DiffLine>>,
+    pub diff_scroll: usize,
+}
+
+impl SwapFiles {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            for job in receiver {
+                // swap files are best effort, a failed write is retried next time
+                let _ = match job {
+                    Job::Write(path, filename, contents) => write_swap(&path, &filename, &contents),
+                    Job::Remove(path) => fs::remove_file(path),
+                };
+            }
+        });
+
+        Self {
+            sender: Some(sender),
+            handle: Some(handle),
+            written: HashMap::new(),
+            last_update: Instant::now(),
+        }
+    }
+
+    fn send(&self, job: Job) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(job);
+        }
+    }
+
+    /// Whether this editor wrote (or took over) the swap file at `path`
+    pub fn owns(&self, path: &Path) -> bool {
+        self.written.contains_key(path)
+    }
+
+    /// Take over a recovered swap file, it's rewritten on the next update
+    pub fn adopt(&mut self, path: PathBuf) {
+        self.written.insert(path, 0);
+    }
+
+    /// Remove every swap file this editor owns and stop the writer thread
+    pub fn shutdown(&mut self) {
+        for path in std::mem::take(&mut self.written).into_keys() {
+            self.send(Job::Remove(path));
+        }
+        self.stop_writer();
+    }
+
+    /// Let the writer thread finish the queued jobs and stop
+    fn stop_writer(&mut self) {
+        self.sender = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Where the swap file for `filename` goes, None for unnamed buffers
+pub fn swap_path(filename: &str) -> Option<PathBuf> {
+    if filename.is_empty() {
+        return None;
+    }
+
+    let path = Path::new(filename);
+    let name = path.file_name()?.to_string_lossy();
+    let dir = path.parent().unwrap_or(Path::new(""));
+    Some(dir.join(format!(".{}.pte.swp", name)))
+}
+
+fn serialize(filename: &str, lines: &[String]) -> String {
+    format!("{}\npid={}\nfile={}\n\n{}", HEADER, process::id(), filename, lines.join("\n"))
+}
+
+/**
+ * The swap file of a large file: its size, to tell if it's still the file
+ * the edits were made to, and a `@@ start end count` line before the lines
+ * of each patch
+ */
+fn serialize_large(filename: &str, size: u64, patches: &[Patch]) -> String {
+    let mut out = format!("{}\npid={}\nfile={}\nlarge={}\n\n", HEADER, process::id(), filename, size);
+    for patch in patches {
+        out.push_str(&format!("@@ {} {} {}\n", patch.start, patch.end, patch.lines.len()));
+        for line in &patch.lines {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn parse_patches(body: &str) -> Option<Vec<Patch>> {
+    let mut patches = Vec::new();
+    let mut lines = body.split('\n');
+    while let Some(header) = lines.next().filter(|line| !line.is_empty()) {
+        let numbers: Vec<usize> = header.strip_prefix("@@ ")?
+            .split(' ')
+            .map(|number| number.parse().ok())
+            .collect::<Option<_>>()?;
+        let [start, end, count] = numbers[..] else {
+            return None;
+        };
+        let text = (0..count).map(|_| lines.next().map(String::from)).collect::<Option<_>>()?;
+        patches.push(Patch { start, end, lines: text });
+    }
+    Some(patches)
+}
+
+/// What goes in the swap file of a buffer showing `lines`
+fn contents(filename: &str, lines: &[String], large: Option<&mut LargeFile>) -> String {
+    match large {
+        Some(large) => {
+            let size = large.size();
+            serialize_large(filename, size, &large.patches(lines))
+        }
+        None => serialize(filename, lines),
+    }
+}
+
+/**
+ * Write the swap file of `filename`
+ *
+ * The swap holds what the file does, so it is only readable by whoever can
+ * read the file (just the user for a new file).
+ */
+fn write_swap(path: &Path, filename: &str, contents: &str) -> io::Result<()> {
+    // write next to it first, so a crash mid-write keeps the previous swap
+    let temp = path.with_extension("swp.tmp");
+    // a leftover temp file would keep its permissions
+    let _ = fs::remove_file(&temp);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        let mode = fs::metadata(filename).map_or(0o600, |metadata| metadata.permissions().mode() & 0o777);
+        options.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = filename;
+
+    let mut file = options.open(&temp)?;
+    file.write_all(contents.as_bytes())?;
+    drop(file);
+    fs::rename(&temp, path)
+}
+
+fn hash_text(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/**
+ * Queue writes for dirty buffers that changed since their last swap, and
+ * removal of swap files for buffers that were saved or closed
+ */
+pub fn update(editor: &mut Editor) {
+    if editor.swap.last_update.elapsed() < SWAP_INTERVAL {
+        return;
+    }
+    editor.swap.last_update = Instant::now();
+
+    let mut dirty = HashSet::new();
+    for i in 0..editor.buffers.len() {
+        let (filename, lines, saved, large) = if i == editor.active_buffer {
+            (&editor.filename, &editor.lines, editor.changes_saved, editor.large.as_mut())
+        } else {
+            let buffer = &mut editor.buffers[i];
+            (&buffer.filename, &buffer.lines, buffer.changes_saved, buffer.large.as_mut())
+        };
+        if saved {
+            continue;
+        }
+        let Some(path) = swap_path(filename) else {
+            continue;
+        };
+
+        let contents = contents(filename, lines, large);
+        let hash = hash_text(&contents);
+        if editor.swap.written.get(&path) != Some(&hash) {
+            editor.swap.send(Job::Write(path.clone(), filename.clone(), contents));
+            editor.swap.written.insert(path.clone(), hash);
+        }
+        dirty.insert(path);
+    }
+
+    let stale: Vec<PathBuf> = editor.swap.written.keys()
+        .filter(|path| !dirty.contains(*path))
+        .cloned()
+        .collect();
+    for path in stale {
+        editor.swap.written.remove(&path);
+        editor.swap.send(Job::Remove(path));
+    }
+}
+
+/**
+ * Look for a swap file left behind for the file that was just opened
+ *
+ * Swap files that match the file on disk are left over from a clean
+ * state and are removed without asking.
+ */
+pub fn find_recovery(editor: &Editor) -> Option<Recovery> {
+    let swap_path = swap_path(&editor.filename)?;
+    if editor.swap.owns(&swap_path) {
+        return None;
+    }
+
+    let contents = fs::read_to_string(&swap_path).ok()?;
+    let mut parts = contents.splitn(2, "\n\n");
+    let header = parts.next()?;
+    let body = parts.next().unwrap_or("");
+
+    let mut header_lines = header.lines();
+    if header_lines.next() != Some(HEADER) {
+        return None;
+    }
+    let pid = header_lines
+        .find_map(|line| line.strip_prefix("pid="))
+        .and_then(|pid| pid.parse::<u32>().ok());
+    let running_pid = pid.filter(|pid| *pid != process::id() && is_running(*pid));
+    let large_size = header.lines()
+        .find_map(|line| line.strip_prefix("large="))
+        .and_then(|size| size.parse::<u64>().ok());
+
+    // the edits of a large file only make sense on the file they were made to
+    let patches = match (large_size, &editor.large) {
+        (Some(size), Some(large)) if size == large.size() => Some(parse_patches(body)?),
+        (None, None) => None,
+        _ => return None,
+    };
+    let lines: Vec<String> = match &patches {
+        Some(patches) => patches.iter().flat_map(|patch| patch.lines.iter().cloned()).collect(),
+        None => body.split('\n').map(String::from).collect(),
+    };
+    let unchanged = match &patches {
+        Some(patches) => patches.is_empty(),
+        None => lines == editor.lines,
+    };
+    if unchanged && running_pid.is_none() {
+        let _ = fs::remove_file(&swap_path);
+        return None;
+    }
+
+    Some(Recovery {
+        filename: editor.filename.clone(),
+        swap_path,
+        lines,
+        patches,
+        running_pid,
+        diff: None,
+        diff_scroll: 0,
+    })
+}
+
+fn is_running(pid: u32) -> bool {
+    Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+/**
+ * Write every buffer with unsaved changes somewhere it can be recovered from
+ *
+ * Used after a panic, so it stops the background thread and writes
+ * directly. Named buffers go to their swap file (and are offered
+ * for recovery the next time they are opened), unnamed ones to the temp dir.
+ */
+pub fn dump_unsaved(editor: &mut Editor) -> Vec<PathBuf> {
+    // the writer thread would race us for the same temp files
+    editor.swap.stop_writer();
+    let mut written = Vec::new();
+    for i in 0..editor.buffers.len() {
+        let (filename, lines, saved, large) = if i == editor.active_buffer {
+            (&editor.filename, &editor.lines, editor.changes_saved, editor.large.as_mut())
+        } else {
+            let buffer = &mut editor.buffers[i];
+            (&buffer.filename, &buffer.lines, buffer.changes_saved, buffer.large.as_mut())
+        };
+        // the unedited lines of a large file can't be read after a crash in them
+        if saved || large.as_ref().is_some_and(|large| !large.intact()) {
+            continue;
+        }
+
+        let result = match swap_path(filename) {
+            Some(path) => write_swap(&path, filename, &contents(filename, lines, large)).map(|_| path),
+            None => {
+                let path = env::temp_dir().join(format!("pte-recovery-{}-{}.txt", process::id(), i + 1));
+                fs::write(&path, lines.join("\n")).map(|_| path)
+            }
+        };
+        if let Ok(path) = result {
+            written.push(path);
+        }
+    }
+    written
+}
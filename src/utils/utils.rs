@@ -5,7 +5,7 @@ use std::{
 
 /// use this function to debug to a file when
 /// we can't debug to the editor
-pub fn dbg(line: &str) -> () {
+pub fn dbg(line: &str) {
     let mut file = match OpenOptions::new()
         .append(true)
         .create(true)
@@ -14,5 +14,5 @@ pub fn dbg(line: &str) -> () {
             Err(_) => return,
         };
 
-    writeln!(file, "{}", line);
+    let _ = writeln!(file, "{}", line);
 }
\ No newline at end of file
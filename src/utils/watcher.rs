@@ -0,0 +1,315 @@
+use crate::Editor;
+use super::{
+    diff::DiffLine,
+    encoding,
+    files::open_file_with_encoding,
+    format
+};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime}
+};
+
+#[cfg(target_os = "linux")]
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+
+/// How often files are checked when inotify isn't available
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, PartialEq)]
+struct FileStamp {
+    mtime: Option<SystemTime>,
+    size: u64,
+}
+
+struct Watched {
+    path: PathBuf,
+    /// None while the file doesn't exist
+    stamp: Option<FileStamp>,
+    hash: u64,
+    /// Large files aren't hashed, only their stamp is compared
+    large: bool,
+}
+
+///
+/// ### Notices when open files are changed by other programs
+///
+/// On Linux the directories of open files are watched with inotify (whole
+/// directories, since atomic saves replace the file). Elsewhere, or if
+/// inotify can't be set up, every file is checked every couple of seconds.
+/// A file only counts as changed if its contents differ from what we know.
+///
+pub struct FileWatcher {
+    #[cfg(target_os = "linux")]
+    inotify: Option<Inotify>,
+    #[cfg(target_os = "linux")]
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    /// Watched files, by buffer filename
+    files: HashMap<String, Watched>,
+    last_poll: Instant,
+}
+
+///
+/// ### A buffer with unsaved changes whose file changed on disk
+///
+pub struct ExternalChange {
+    pub filename: String,
+    /// The file as it is on disk now
+    pub lines: Vec<String>,
+    pub diff: Option<Vec<DiffLine>>,
+    pub diff_scroll: usize,
+}
+
+impl FileWatcher {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(target_os = "linux")]
+            inotify: Inotify::init().ok(),
+            #[cfg(target_os = "linux")]
+            dirs: HashMap::new(),
+            files: HashMap::new(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    fn watch(&mut self, filename: &str, large: bool) {
+        let path = fs::canonicalize(filename).unwrap_or_else(|_| PathBuf::from(filename));
+        let (stamp, hash) = match large {
+            true => (stamp(&path), 0),
+            false => read_state(&path),
+        };
+
+        #[cfg(target_os = "linux")]
+        if let (Some(inotify), Some(dir)) = (&self.inotify, path.parent()) {
+            if !self.dirs.values().any(|d| d == dir) {
+                let mask = WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE
+                    | WatchMask::DELETE | WatchMask::MOVED_FROM | WatchMask::MODIFY;
+                if let Ok(wd) = inotify.watches().add(dir, mask) {
+                    self.dirs.insert(wd, dir.to_path_buf());
+                }
+            }
+        }
+
+        self.files.insert(filename.to_string(), Watched { path, stamp, hash, large });
+    }
+
+    /**
+     * Take the file as it is on disk now as known, after we wrote it
+     *
+     * Needed for large files, whose contents aren't compared.
+     */
+    pub fn refresh(&mut self, filename: &str) {
+        if let Some(watched) = self.files.get_mut(filename) {
+            watched.stamp = stamp(&watched.path);
+        }
+    }
+
+    /// Filenames of watched files that may have changed since the last call
+    fn candidates(&mut self) -> Vec<String> {
+        #[cfg(target_os = "linux")]
+        if let Some(inotify) = &mut self.inotify {
+            let mut buffer = [0; 4096];
+            let mut touched = HashSet::new();
+            // read_events doesn't block, it errors once there is nothing left
+            while let Ok(events) = inotify.read_events(&mut buffer) {
+                let mut any = false;
+                for event in events {
+                    any = true;
+                    if let (Some(dir), Some(name)) = (self.dirs.get(&event.wd), event.name) {
+                        touched.insert(dir.join(name));
+                    }
+                }
+                if !any {
+                    break;
+                }
+            }
+
+            return self.files.iter()
+                .filter(|(_, watched)| touched.contains(&watched.path))
+                .map(|(filename, _)| filename.clone())
+                .collect();
+        }
+
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+        self.files.keys().cloned().collect()
+    }
+}
+
+fn stamp(path: &Path) -> Option<FileStamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(FileStamp { mtime: metadata.modified().ok(), size: metadata.len() })
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn read_state(path: &Path) -> (Option<FileStamp>, u64) {
+    let stamp = stamp(path);
+    let hash = fs::read(path).map(|bytes| hash_bytes(&bytes)).unwrap_or(0);
+    (stamp, hash)
+}
+
+/**
+ * Check open files for changes made by other programs
+ *
+ * Clean buffers are reloaded right away, dirty ones are queued in
+ * `editor.external_changes` for the user to decide on.
+ */
+pub fn check(editor: &mut Editor) {
+    // keep the watched files in sync with the open buffers
+    let open: HashMap<String, bool> = (0..editor.buffers.len())
+        .map(|i| if i == editor.active_buffer {
+            (editor.filename.clone(), editor.large.is_some())
+        } else {
+            (editor.buffers[i].filename.clone(), editor.buffers[i].large.is_some())
+        })
+        .filter(|(filename, _)| !filename.is_empty())
+        .collect();
+    editor.watcher.files.retain(|filename, watched| open.get(filename) == Some(&watched.large));
+    for (filename, large) in &open {
+        if !editor.watcher.files.contains_key(filename) {
+            editor.watcher.watch(filename, *large);
+        }
+    }
+
+    for filename in editor.watcher.candidates() {
+        let Some(watched) = editor.watcher.files.get_mut(&filename) else {
+            continue;
+        };
+        let new_stamp = stamp(&watched.path);
+        if new_stamp == watched.stamp {
+            continue;
+        }
+        watched.stamp = new_stamp;
+        if watched.large {
+            if new_stamp.is_none() {
+                file_deleted(editor, &filename);
+            } else {
+                large_changed(editor, &filename);
+            }
+            continue;
+        }
+
+        let Ok(bytes) = fs::read(&watched.path) else {
+            if new_stamp.is_none() {
+                file_deleted(editor, &filename);
+            }
+            continue;
+        };
+        let hash = hash_bytes(&bytes);
+        if hash == watched.hash {
+            continue;
+        }
+        watched.hash = hash;
+
+        let Some(index) = editor.find_buffer(&filename) else {
+            continue;
+        };
+        let (lines, file_format, saved) = if index == editor.active_buffer {
+            (&editor.lines, &editor.format, editor.changes_saved)
+        } else {
+            let buffer = &editor.buffers[index];
+            (&buffer.lines, &buffer.format, buffer.changes_saved)
+        };
+
+        // our own save, or someone wrote exactly what we have
+        let contents = format::encode(lines, file_format);
+        if encoding::encode(&contents, file_format.encoding).is_ok_and(|ours| ours == bytes) {
+            continue;
+        }
+
+        if saved {
+            reload(editor, index);
+            editor.notif_text = format!("Reloaded {}, it changed on disk", filename);
+        } else {
+            let (text, _) = encoding::decode(&bytes, file_format.encoding);
+            let (disk_lines, _) = format::decode(&text);
+            editor.external_changes.retain(|change| change.filename != filename);
+            editor.external_changes.push(ExternalChange {
+                filename,
+                lines: disk_lines,
+                diff: None,
+                diff_scroll: 0,
+            });
+        }
+    }
+}
+
+fn file_deleted(editor: &mut Editor, filename: &str) {
+    let Some(index) = editor.find_buffer(filename) else {
+        return;
+    };
+    // so the buffer isn't lost without a prompt when quitting
+    if index == editor.active_buffer {
+        editor.changes_saved = false;
+    } else {
+        editor.buffers[index].changes_saved = false;
+    }
+    editor.notif_text = format!("{} was deleted on disk", filename);
+}
+
+/**
+ * A large file changed on disk, which can't be compared with the buffer
+ *
+ * A clean buffer is reloaded, before its old mapping is read again. A dirty
+ * one keeps its edits, but if the file was truncated its unedited lines
+ * can't be read anymore.
+ */
+fn large_changed(editor: &mut Editor, filename: &str) {
+    let Some(index) = editor.find_buffer(filename) else {
+        return;
+    };
+    let (large, saved) = if index == editor.active_buffer {
+        (&editor.large, editor.changes_saved)
+    } else {
+        (&editor.buffers[index].large, editor.buffers[index].changes_saved)
+    };
+    let intact = large.as_ref().is_none_or(|large| large.intact());
+
+    editor.notif_text = if saved {
+        reload(editor, index);
+        format!("Reloaded {}, it changed on disk", filename)
+    } else if intact {
+        format!("{} changed on disk, saving will overwrite it", filename)
+    } else {
+        format!("{} was truncated on disk, only the loaded lines are left", filename)
+    };
+}
+
+/**
+ * Read a buffer's file from disk again, keeping the cursors and scroll
+ * position and the encoding it was read with
+ */
+pub fn reload(editor: &mut Editor, index: usize) {
+    let previous = editor.active_buffer;
+    editor.switch_buffer(index);
+
+    let cursors = editor.cursors.clone();
+    let scroll = editor.scroll;
+    let notif_text = editor.notif_text.clone();
+    // large files are only read as UTF-8
+    let forced = editor.large.is_none().then_some(editor.format.encoding);
+    open_file_with_encoding(editor, forced);
+    editor.cursors = cursors;
+    editor.scroll = scroll;
+    editor.adjust_cursors();
+    editor.notif_text = notif_text;
+
+    if let Some(watched) = editor.watcher.files.get_mut(&editor.filename) {
+        watched.large = editor.large.is_some();
+        (watched.stamp, watched.hash) = match watched.large {
+            true => (stamp(&watched.path), 0),
+            false => read_state(&watched.path),
+        };
+    }
+    editor.switch_buffer(previous);
+}